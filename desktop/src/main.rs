@@ -1,6 +1,17 @@
 use anyhow::Result;
+use nopt::cartridge::Cartridge as _;
+use std::{
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _};
 
+/// How often battery-backed RAM is written to disk while running, in frames.
+const BATTERY_RAM_FLUSH_INTERVAL_FRAMES: u32 = 60;
+
 fn main() -> Result<()> {
     tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer())
@@ -14,12 +25,44 @@ fn main() -> Result<()> {
     let Some(rom_filepath) = std::env::args().nth(1) else {
         panic!("missing argument: rom filepath");
     };
-    let rom: Vec<u8> = std::fs::read(rom_filepath).unwrap();
+    let rom: Vec<u8> = std::fs::read(&rom_filepath).unwrap();
+
+    // battery-backed RAM is kept next to the ROM
+    let save_filepath = Path::new(&rom_filepath).with_extension("sav");
+    let battery_ram = match std::fs::read(&save_filepath) {
+        Ok(battery_ram) => battery_ram,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut runtime = nopt::Nopt::new(nopt::cartridge::from_bytes_with_header_and_battery_ram(
+        &rom,
+        &battery_ram,
+    ));
+
+    let is_exit_requested = Arc::new(AtomicBool::new(false));
+    ctrlc::set_handler({
+        let is_exit_requested = Arc::clone(&is_exit_requested);
+        move || is_exit_requested.store(true, Ordering::Relaxed)
+    })?;
+
+    let mut saved_battery_ram = battery_ram;
+    let mut flush_battery_ram =
+        |runtime: &nopt::Nopt<nopt::cartridge::AnyCartridge>| -> Result<()> {
+            if let Some(battery_ram) = runtime.nes().cartridge.battery_ram()
+                && battery_ram != saved_battery_ram
+            {
+                std::fs::write(&save_filepath, battery_ram)?;
+                saved_battery_ram = battery_ram.to_vec();
+            }
+            Ok(())
+        };
 
-    let mut runtime = nopt::Nopt::new(nopt::cartridge::from_bytes_with_header(&rom));
-    unsafe {
-        loop {
-            runtime.run();
+    while !is_exit_requested.load(Ordering::Relaxed) {
+        for _ in 0..BATTERY_RAM_FLUSH_INTERVAL_FRAMES {
+            runtime.run_frame();
         }
+        flush_battery_ram(&runtime)?;
     }
+    flush_battery_ram(&runtime)
 }
@@ -2,12 +2,13 @@ mod cranelift_backend;
 pub(crate) mod frontend;
 mod ir;
 
-use crate::compiler::frontend::nes::Nes;
+use crate::{compiler::frontend::nes::Nes, nes_assembly::Mnemonic};
 use iced_x86::Formatter as _;
 use memmap2::Mmap;
 use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
+    mem::offset_of,
     rc::Rc,
 };
 use tracing::trace;
@@ -24,8 +25,8 @@ impl Compiler {
     pub(crate) fn compile<Cartridge: crate::cartridge::Cartridge>(
         &self,
         nes: &mut Nes<Cartridge>,
-    ) -> (Mmap, bool) {
-        let (ir, is_prg_rom_only) = frontend::compile_instruction(nes, nes.cpu.pc);
+    ) -> (Mmap, Mnemonic, bool) {
+        let (ir, mnemonic, is_prg_rom_only) = frontend::compile_instruction(nes, nes.cpu.pc);
         Self::trace_ir_function(&ir);
 
         let bytes = cranelift_backend::compile(&ir, self.optimize);
@@ -33,7 +34,7 @@ impl Compiler {
         let mut decoder = iced_x86::Decoder::new(64, &bytes, iced_x86::DecoderOptions::NONE);
         decoder.set_ip(bytes.as_ptr() as u64);
         let mut formatter = iced_x86::IntelFormatter::with_options(
-            Some(Box::new(NesStateSymbolResolver::new(nes))),
+            Some(Box::new(NesStateSymbolResolver::new::<Cartridge>())),
             None,
         );
         for instruction in decoder {
@@ -42,7 +43,7 @@ impl Compiler {
             trace!("native: {formatted_instruction}");
         }
 
-        (bytes, is_prg_rom_only)
+        (bytes, mnemonic, is_prg_rom_only)
     }
 
     fn trace_ir_function(function: &ir::Function) {
@@ -84,18 +85,24 @@ impl Compiler {
     }
 }
 
+/// Names the fields of the machine state by their offsets, which compiled
+/// code uses as displacements from the state pointer.
 struct NesStateSymbolResolver(HashMap<u64, &'static str>);
 
 impl NesStateSymbolResolver {
-    pub(crate) fn new<Cartridge: crate::cartridge::Cartridge>(nes: *mut Nes<Cartridge>) -> Self {
+    pub(crate) fn new<Cartridge: crate::cartridge::Cartridge>() -> Self {
         let mut mapping = HashMap::new();
-        mapping.insert(unsafe { &raw const (*nes).cpu.a as u64 }, "cpu_a");
-        mapping.insert(unsafe { &raw const (*nes).cpu.x as u64 }, "cpu_x");
-        mapping.insert(unsafe { &raw const (*nes).cpu.y as u64 }, "cpu_y");
-        mapping.insert(unsafe { &raw const (*nes).cpu.s as u64 }, "cpu_s");
-        mapping.insert(unsafe { &raw const (*nes).cpu.p as u64 }, "cpu_p");
-        mapping.insert(unsafe { &raw const (*nes).cpu.pc as u64 }, "cpu_pc");
-        mapping.insert(unsafe { (*nes).cpu.ram.as_ptr() as u64 }, "cpu_ram");
+        for (offset, symbol) in [
+            (offset_of!(Nes<Cartridge>, cpu.a), "cpu_a"),
+            (offset_of!(Nes<Cartridge>, cpu.x), "cpu_x"),
+            (offset_of!(Nes<Cartridge>, cpu.y), "cpu_y"),
+            (offset_of!(Nes<Cartridge>, cpu.s), "cpu_s"),
+            (offset_of!(Nes<Cartridge>, cpu.p), "cpu_p"),
+            (offset_of!(Nes<Cartridge>, cpu.pc), "cpu_pc"),
+            (offset_of!(Nes<Cartridge>, cpu.ram), "cpu_ram"),
+        ] {
+            mapping.insert(u64::try_from(offset).unwrap(), symbol);
+        }
         Self(mapping)
     }
 }
@@ -2,8 +2,11 @@ use crate::compiler::ir;
 use cranelift_codegen::{
     Context,
     control::ControlPlane,
-    ir::{Block, Function, InstBuilder, MemFlags, Type, Value, condcodes::IntCC},
-    isa::TargetIsa,
+    ir::{
+        AbiParam, Block, Function, InstBuilder, MemFlags, Signature, Type, UserFuncName, Value,
+        condcodes::IntCC,
+    },
+    isa::{CallConv, TargetIsa},
     settings::{self, Configurable as _},
 };
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
@@ -66,13 +69,20 @@ impl Compiler {
     }
 
     pub(crate) fn compile(mut self, ir: &ir::Function) -> Mmap {
-        let mut function = Function::new();
+        // the compiled function takes a pointer to the machine state as its
+        // only argument
+        let mut signature = Signature::new(CallConv::triple_default(self.isa.triple()));
+        signature
+            .params
+            .push(AbiParam::new(self.isa.pointer_type()));
+        let mut function = Function::with_name_signature(UserFuncName::default(), signature);
         let mut function_builder_context = FunctionBuilderContext::new();
         let mut function_builder =
             FunctionBuilder::new(&mut function, &mut function_builder_context);
 
         let entry_block = function_builder.create_block();
-        self.compile_block(&mut function_builder, entry_block, &ir.basic_block);
+        let state = function_builder.append_block_param(entry_block, self.isa.pointer_type());
+        self.compile_block(&mut function_builder, entry_block, &ir.basic_block, state);
 
         function_builder.seal_all_blocks();
         function_builder.finalize();
@@ -98,6 +108,7 @@ impl Compiler {
         function_builder: &mut FunctionBuilder,
         block: Block,
         ir: &Rc<RefCell<ir::BasicBlock>>,
+        state: Value,
     ) {
         let type_u8 = Type::int(8).unwrap();
         let type_u16 = Type::int(16).unwrap();
@@ -224,20 +235,19 @@ impl Compiler {
                 } => {
                     let value = match definition {
                         ir::Definition8::BasicBlockArgument => argument.unwrap(),
-                        ir::Definition8::NativeMemory { address, offset } => {
-                            let address = function_builder
-                                .ins()
-                                .iconst(self.isa.pointer_type(), *address as i64);
+                        ir::Definition8::NativeMemory {
+                            state_offset,
+                            offset,
+                        } => {
                             let offset = function_builder
                                 .ins()
                                 .uextend(self.isa.pointer_type(), self.value_16(*offset));
-                            let address_plus_offset =
-                                function_builder.ins().uadd_overflow(address, offset).0;
+                            let state_plus_offset = function_builder.ins().iadd(state, offset);
                             function_builder.ins().load(
                                 type_u8,
                                 MemFlags::new(),
-                                address_plus_offset,
-                                0,
+                                state_plus_offset,
+                                i32::try_from(*state_offset).unwrap(),
                             )
                         }
                         ir::Definition8::Immediate(immediate) => function_builder
@@ -342,24 +352,44 @@ impl Compiler {
                     self.variable_16_mapping.insert(variable.id, value);
                 }
                 ir::Instruction::Store8 {
-                    destination: ir::Destination8::NativeMemory { address, offset },
+                    destination:
+                        ir::Destination8::NativeMemory {
+                            state_offset,
+                            offset,
+                        },
                     variable,
                 } => {
-                    let address = function_builder
-                        .ins()
-                        .iconst(self.isa.pointer_type(), *address as i64);
                     let offset = function_builder
                         .ins()
                         .uextend(self.isa.pointer_type(), self.value_16(*offset));
-                    let address_plus_offset =
-                        function_builder.ins().uadd_overflow(address, offset).0;
+                    let state_plus_offset = function_builder.ins().iadd(state, offset);
                     function_builder.ins().store(
                         MemFlags::new(),
                         self.value_8(*variable),
-                        address_plus_offset,
-                        0,
+                        state_plus_offset,
+                        i32::try_from(*state_offset).unwrap(),
                     );
                 }
+                ir::Instruction::CallNative {
+                    function,
+                    argument_state_offset,
+                } => {
+                    let mut signature = Signature::new(CallConv::triple_default(self.isa.triple()));
+                    signature
+                        .params
+                        .push(AbiParam::new(self.isa.pointer_type()));
+                    let signature = function_builder.import_signature(signature);
+
+                    let function = function_builder
+                        .ins()
+                        .iconst(self.isa.pointer_type(), *function as *const () as i64);
+                    let argument = function_builder
+                        .ins()
+                        .iadd_imm(state, i64::try_from(*argument_state_offset).unwrap());
+                    function_builder
+                        .ins()
+                        .call_indirect(signature, function, &[argument]);
+                }
             }
         }
 
@@ -418,7 +448,7 @@ impl Compiler {
                 );
 
                 for (block, ir) in blocks_to_compile {
-                    self.compile_block(function_builder, block, ir);
+                    self.compile_block(function_builder, block, ir, state);
                 }
             }
         }
@@ -1,40 +1,77 @@
 mod instruction_decoder;
 pub(crate) mod nes;
 
-use crate::compiler::{
-    frontend::nes::{Cpu, Nes, Visitor},
-    ir::{
-        BasicBlock, Definition1, Definition8, Definition16, Destination8, Function, Instruction,
-        Jump, Variable1, Variable8, Variable16,
+use crate::{
+    compiler::{
+        frontend::nes::{Cpu, InterpreterVisitor, Nes, Visitor},
+        ir::{
+            BasicBlock, Definition1, Definition8, Definition16, Destination8, Function,
+            Instruction, Jump, Variable1, Variable8, Variable16,
+        },
     },
+    nes_assembly::Mnemonic,
 };
-use std::{cell::RefCell, rc::Rc, sync::atomic::AtomicUsize};
+use std::{cell::RefCell, collections::HashMap, ops::Range, rc::Rc, sync::atomic::AtomicUsize};
 
 pub(super) fn compile_instruction<Cartridge: crate::cartridge::Cartridge>(
     nes: &mut Nes<Cartridge>,
     address: u16,
-) -> (Function, bool) {
+) -> (Function, Mnemonic, bool) {
     let (cpu_instruction, is_prg_rom_only) = instruction_decoder::decode_instruction(nes, address);
 
     let basic_block = Rc::new(RefCell::new(BasicBlock::new(Rc::new(AtomicUsize::new(0)))));
+    let state_start = (&raw const *nes).addr();
+    let state = state_start..state_start + size_of::<Nes<Cartridge>>();
     Cpu::compile(
         nes,
         CompilerVisitor {
             current_block: Rc::clone(&basic_block),
             exit_block: None,
+            state,
+            immediates_u16: Rc::new(RefCell::new(HashMap::new())),
         },
         &cpu_instruction,
     );
 
-    (Function { basic_block }, is_prg_rom_only)
+    (
+        Function { basic_block },
+        cpu_instruction.operation().mnemonic(),
+        is_prg_rom_only,
+    )
+}
+
+/// Runs the instruction at the given address directly, without compiling it,
+/// returning its mnemonic.
+pub(crate) fn interpret_instruction<Cartridge: crate::cartridge::Cartridge>(
+    nes: &mut Nes<Cartridge>,
+    address: u16,
+) -> Mnemonic {
+    let (cpu_instruction, _) = instruction_decoder::decode_instruction(nes, address);
+    Cpu::compile(nes, InterpreterVisitor::new(), &cpu_instruction);
+    cpu_instruction.operation().mnemonic()
 }
 
 pub(crate) struct CompilerVisitor {
     current_block: Rc<RefCell<BasicBlock>>,
     exit_block: Option<Rc<RefCell<BasicBlock>>>,
+    /// The host addresses of the machine state being compiled for.
+    state: Range<usize>,
+    /// The values of the 16-bit variables defined as immediates, by ID.
+    immediates_u16: Rc<RefCell<HashMap<usize, u16>>>,
 }
 
 impl CompilerVisitor {
+    /// Converts a pointer into the machine state to an offset from its start,
+    /// which keeps compiled code independent of where the state is located.
+    fn state_offset(&self, address: *const u8) -> usize {
+        let address = address.addr();
+        assert!(
+            self.state.contains(&address),
+            "compiled code may only access the machine state"
+        );
+        address - self.state.start
+    }
+
     fn define_1(&mut self, definition: Definition1) -> Variable1 {
         self.current_block.borrow_mut().define_1(definition)
     }
@@ -47,6 +84,13 @@ impl CompilerVisitor {
         self.current_block.borrow_mut().define_16(definition)
     }
 
+    fn push_instruction(&mut self, instruction: Instruction) {
+        self.current_block
+            .borrow_mut()
+            .instructions
+            .push(instruction);
+    }
+
     fn store_8(&mut self, destination: Destination8, value: Variable8) {
         self.current_block
             .borrow_mut()
@@ -67,8 +111,25 @@ impl Visitor for CompilerVisitor {
         self.define_8(Definition8::Immediate(value))
     }
 
+    fn immediate_u16(&mut self, value: u16) -> Variable16 {
+        let [high, low] = value.to_be_bytes();
+        let high = self.immediate_u8(high);
+        let low = self.immediate_u8(low);
+        let variable = self.concatenate(high, low);
+        self.immediates_u16.borrow_mut().insert(variable.id, value);
+        variable
+    }
+
+    fn known_u16(&self, value: Variable16) -> Option<u16> {
+        self.immediates_u16.borrow().get(&value.id).copied()
+    }
+
     fn memory_with_offset_u8(&mut self, address: *const u8, offset: Variable16) -> Variable8 {
-        self.define_8(Definition8::NativeMemory { address, offset })
+        let state_offset = self.state_offset(address);
+        self.define_8(Definition8::NativeMemory {
+            state_offset,
+            offset,
+        })
     }
 
     fn set_memory_with_offset_u8(
@@ -77,7 +138,22 @@ impl Visitor for CompilerVisitor {
         offset: Variable16,
         value: Variable8,
     ) {
-        self.store_8(Destination8::NativeMemory { address, offset }, value);
+        let state_offset = self.state_offset(address);
+        self.store_8(
+            Destination8::NativeMemory {
+                state_offset,
+                offset,
+            },
+            value,
+        );
+    }
+
+    fn call_native(&mut self, function: unsafe extern "C" fn(*mut u8), argument: *mut u8) {
+        let argument_state_offset = self.state_offset(argument);
+        self.push_instruction(Instruction::CallNative {
+            function,
+            argument_state_offset,
+        });
     }
 
     fn get_bit(&mut self, value: Variable8, bit_index: u8) -> Variable1 {
@@ -253,6 +329,8 @@ impl Visitor for CompilerVisitor {
         visit_true(CompilerVisitor {
             current_block: Rc::clone(&true_block),
             exit_block: Some(Rc::clone(&exit_block)),
+            state: self.state.clone(),
+            immediates_u16: Rc::clone(&self.immediates_u16),
         });
 
         let false_block = Rc::new(RefCell::new(BasicBlock::new(Rc::clone(
@@ -261,6 +339,8 @@ impl Visitor for CompilerVisitor {
         visit_false(CompilerVisitor {
             current_block: Rc::clone(&false_block),
             exit_block: Some(Rc::clone(&exit_block)),
+            state: self.state.clone(),
+            immediates_u16: Rc::clone(&self.immediates_u16),
         });
 
         self.current_block.borrow_mut().jump = Jump::BasicBlock {
@@ -293,6 +373,8 @@ impl Visitor for CompilerVisitor {
         visit_true(CompilerVisitor {
             current_block: Rc::clone(&true_block),
             exit_block: Some(Rc::clone(&exit_block)),
+            state: self.state.clone(),
+            immediates_u16: Rc::clone(&self.immediates_u16),
         });
 
         let false_block = Rc::new(RefCell::new(BasicBlock::new(Rc::clone(
@@ -301,6 +383,8 @@ impl Visitor for CompilerVisitor {
         visit_false(CompilerVisitor {
             current_block: Rc::clone(&false_block),
             exit_block: Some(Rc::clone(&exit_block)),
+            state: self.state.clone(),
+            immediates_u16: Rc::clone(&self.immediates_u16),
         });
 
         self.current_block.borrow_mut().jump = Jump::BasicBlock {
@@ -1,16 +1,45 @@
+mod apu;
 pub mod cartridge;
+pub mod cheat;
 mod cpu;
+mod frame;
+pub mod input_device;
+mod interpreter_visitor;
 mod ppu;
+pub mod save_state;
 mod visitor;
+pub mod watchpoint;
 
+pub(crate) use apu::Apu;
 pub(crate) use cpu::Cpu;
+pub use frame::Frame;
+pub use input_device::Buttons;
+pub(crate) use interpreter_visitor::InterpreterVisitor;
 pub(crate) use ppu::Ppu;
 pub(crate) use visitor::Visitor;
 
+use cheat::Cheat;
+use input_device::{InputContext, InputDevice, StandardController};
+use save_state::{LoadStateError, SaveState, StateReader, StateWriter};
+use std::any::Any;
+use watchpoint::Watchpoint;
+
+#[derive(Clone)]
 pub struct Nes<Cartridge: cartridge::Cartridge> {
     pub cartridge: Cartridge,
     pub cpu: Cpu,
     pub ppu: Ppu,
+    pub apu: Apu,
+    pub input_devices: [Box<dyn InputDevice>; 2],
+    /// The value being read from an input device by compiled code.
+    input_port_value: u8,
+    pub(crate) cheats: Vec<Cheat>,
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    /// Whether compiled code has triggered a watchpoint since the flag was
+    /// last cleared, along with the first access which did.
+    pub(crate) is_watchpoint_hit: bool,
+    pub(crate) watchpoint_address: u16,
+    pub(crate) watchpoint_value: u8,
 }
 
 impl<Cartridge: cartridge::Cartridge> Nes<Cartridge> {
@@ -20,6 +49,17 @@ impl<Cartridge: cartridge::Cartridge> Nes<Cartridge> {
             cartridge,
             cpu: Cpu::new(cpu_pc),
             ppu: Ppu::new(),
+            apu: Apu::new(),
+            input_devices: [
+                Box::new(StandardController::new()),
+                Box::new(StandardController::new()),
+            ],
+            input_port_value: 0,
+            cheats: Vec::new(),
+            watchpoints: Vec::new(),
+            is_watchpoint_hit: false,
+            watchpoint_address: 0,
+            watchpoint_value: 0,
         }
     }
 
@@ -28,7 +68,333 @@ impl<Cartridge: cartridge::Cartridge> Nes<Cartridge> {
             0..0x2000 => self.cpu.ram[usize::from(address) & 0x7ff],
             0x2000..0x6000 => unimplemented!("peek 0x{address:04x}"),
             0x6000..0x8000 => self.cartridge.peek_prg_ram(address & 0x1fff),
-            0x8000..=0xffff => self.cartridge.peek_prg_rom(address & 0x7fff),
+            0x8000..=0xffff => Cheat::patch(
+                &self.cheats,
+                address,
+                self.cartridge.peek_prg_rom(address & 0x7fff),
+            ),
+        }
+    }
+
+    /// The most recent picture output by the PPU. Its rendering is complete
+    /// whenever the PPU is in vblank.
+    #[must_use]
+    pub fn frame(&self) -> &Frame {
+        &self.ppu.frame
+    }
+
+    /// Plugs a device into the given port (0 or 1), replacing the one
+    /// previously plugged into it.
+    pub fn set_input_device(&mut self, port: usize, device: impl InputDevice) {
+        self.input_devices[port] = Box::new(device);
+    }
+
+    /// The device plugged into the given port (0 or 1), if it is of type
+    /// `Device`.
+    #[must_use]
+    pub fn input_device_mut<Device: InputDevice>(&mut self, port: usize) -> Option<&mut Device> {
+        (self.input_devices[port].as_mut() as &mut dyn Any).downcast_mut()
+    }
+
+    /// Sets the buttons held on the standard controller plugged into the
+    /// given port (0 or 1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the device plugged into the port is not a standard
+    /// controller.
+    pub fn set_controller_state(&mut self, port: usize, buttons: Buttons) {
+        self.input_device_mut::<StandardController>(port)
+            .expect("a standard controller should be plugged into the port")
+            .set_buttons(buttons);
+    }
+
+    /// Captures the state of the whole machine as a versioned binary blob.
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        self.write_state(&mut writer);
+        writer.into_bytes()
+    }
+
+    fn write_state(&self, writer: &mut StateWriter) {
+        self.cpu.save_state(writer);
+        self.ppu.save_state(writer);
+        self.apu.save_state(writer);
+        self.cartridge.save_state(writer);
+        for device in &self.input_devices {
+            device.save_state(writer);
+        }
+    }
+
+    /// Restores a state produced by [`Nes::save_state`]. The machine is left
+    /// untouched if the state cannot be loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state was produced by an incompatible version,
+    /// or for a machine with a different cartridge or input devices.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), LoadStateError> {
+        let mut reader = StateReader::new(state)?;
+        let mut writer = StateWriter::counting();
+        self.write_state(&mut writer);
+        if state.len() != writer.len() {
+            return Err(LoadStateError::SizeMismatch);
+        }
+
+        self.cpu.load_state(&mut reader)?;
+        self.ppu.load_state(&mut reader)?;
+        self.apu.load_state(&mut reader)?;
+        self.cartridge.load_state(&mut reader)?;
+        for device in &mut self.input_devices {
+            device.load_state(&mut reader)?;
+        }
+        debug_assert!(reader.is_empty());
+        Ok(())
+    }
+
+    /// Captures the state needed to continue running the machine, leaving
+    /// out the picture and the input devices, which a save state includes.
+    pub(crate) fn save_snapshot(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        self.cpu.save_state(&mut writer);
+        self.ppu.save_state_without_frame(&mut writer);
+        // the APU keeps the rest of the machine in step, besides raising
+        // interrupts
+        self.apu.save_state(&mut writer);
+        self.cartridge.save_state(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Restores a snapshot produced by [`Nes::save_snapshot`] for this
+    /// machine.
+    pub(crate) fn load_snapshot(&mut self, snapshot: &[u8]) {
+        let load = |nes: &mut Self| {
+            let mut reader = StateReader::new(snapshot)?;
+            nes.cpu.load_state(&mut reader)?;
+            nes.ppu.load_state_without_frame(&mut reader)?;
+            nes.apu.load_state(&mut reader)?;
+            nes.cartridge.load_state(&mut reader)?;
+            debug_assert!(reader.is_empty());
+            Ok::<_, LoadStateError>(())
+        };
+        load(self).expect("snapshots should only be loaded into the machine they came from");
+    }
+
+    /// Sets the rate of the audio output, which defaults to 48 kHz.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    #[must_use]
+    pub fn available_audio_samples(&self) -> usize {
+        self.apu.available_samples()
+    }
+
+    /// Moves generated audio samples into the given buffer, returning how many
+    /// were written. If audio is not drained, only around the last second of
+    /// it is kept.
+    pub fn drain_audio(&mut self, samples: &mut [f32]) -> usize {
+        self.apu.read_samples(samples)
+    }
+
+    /// Accounts for the cycles of the instruction which has just been run,
+    /// then performs any DMA transfers and interrupts it triggered. Returns
+    /// whether an interrupt was taken.
+    pub(crate) fn finish_instruction(&mut self) -> bool {
+        self.finish_cycles();
+
+        if self.cpu.is_oam_dma_pending {
+            Cpu::run_oam_dma(self, &mut InterpreterVisitor::new());
+            // one extra cycle is needed for alignment on odd cycles
+            self.cpu.cycle_count += 513 + (self.cpu.cycle_count & 1);
+            self.catch_up(self.cpu.cycle_count);
+        }
+
+        if self.ppu.is_nmi_pending {
+            self.ppu.is_nmi_pending = false;
+            Cpu::interrupt(self, &mut InterpreterVisitor::new(), 0xfffa);
+            self.finish_cycles();
+            true
+        } else if self.apu.is_irq_asserted() && self.cpu.p & 0b0000_0100 == 0 {
+            Cpu::interrupt(self, &mut InterpreterVisitor::new(), 0xfffe);
+            self.finish_cycles();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn finish_cycles(&mut self) {
+        self.cpu.cycle_count += u64::from(self.cpu.instruction_cycles);
+        self.cpu.instruction_cycles = 0;
+        self.catch_up(self.cpu.cycle_count);
+    }
+
+    /// Runs the rest of the machine up to the start of the given CPU cycle.
+    fn catch_up(&mut self, mut cpu_cycle: u64) {
+        while self.apu.cycle_count < cpu_cycle {
+            for _ in 0..3 {
+                self.ppu.step(&self.cartridge);
+            }
+            self.apu.step();
+
+            if let Some(address) = self.apu.dmc_dma_address() {
+                let value =
+                    Cpu::read_without_watchpoints(self, &mut InterpreterVisitor::new(), address);
+                self.apu.fill_dmc_sample_buffer(value);
+
+                // the CPU is stalled during the transfer, delaying the rest
+                // of the current instruction
+                self.cpu.cycle_count += 4;
+                cpu_cycle += 4;
+            }
+        }
+    }
+
+    pub(super) fn read_input_device<Visitor: self::Visitor, const PORT: usize>(
+        nes: &mut Self,
+        visitor: &mut Visitor,
+    ) -> Visitor::U8 {
+        visitor.call_native(Self::catch_up_before_access, (&raw mut *nes).cast());
+        visitor.call_native(
+            Self::read_input_device_from_compiled_code::<PORT>,
+            (&raw mut *nes).cast(),
+        );
+        visitor.memory_u8(&raw const nes.input_port_value)
+    }
+
+    pub(super) fn write_input_devices<Visitor: self::Visitor>(
+        nes: &mut Self,
+        visitor: &mut Visitor,
+        value: Visitor::U8,
+    ) {
+        visitor.set_memory_u8(&raw mut nes.input_port_value, value);
+        visitor.call_native(
+            Self::write_input_devices_from_compiled_code,
+            (&raw mut *nes).cast(),
+        );
+    }
+
+    unsafe extern "C" fn read_input_device_from_compiled_code<const PORT: usize>(nes: *mut u8) {
+        let nes = unsafe { &mut *nes.cast::<Self>() };
+        let context = InputContext {
+            frame: &nes.ppu.frame,
+            scanline: nes.ppu.scanline,
+            dot: nes.ppu.dot,
+        };
+        nes.input_port_value = nes.input_devices[PORT].read(&context) & 0b1_1111;
+    }
+
+    unsafe extern "C" fn write_input_devices_from_compiled_code(nes: *mut u8) {
+        let nes = unsafe { &mut *nes.cast::<Self>() };
+        for device in &mut nes.input_devices {
+            device.write(nes.input_port_value);
+        }
+    }
+
+    /// Called by compiled code right before a memory access which may
+    /// observe or affect the rest of the machine. The access is assumed to
+    /// take place during the last cycle of the current instruction.
+    pub(crate) unsafe extern "C" fn catch_up_before_access(nes: *mut u8) {
+        let nes = unsafe { &mut *nes.cast::<Self>() };
+        let cpu_cycle = nes.cpu.cycle_count + u64::from(nes.cpu.instruction_cycles);
+        nes.catch_up(cpu_cycle.saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use save_state::VERSION;
+
+    // SEI; loop: INC $00; INC $6000; JMP loop
+    const PROGRAM: [u8; 10] = [0x78, 0xe6, 0x00, 0xee, 0x00, 0x60, 0x4c, 0x01, 0x80, 0x00];
+
+    #[test]
+    fn save_state_round_trips() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        nopt.run_frame();
+        let state = nopt.nes().save_state();
+
+        for _ in 0..1000 {
+            nopt.run();
+        }
+        assert_ne!(nopt.nes().save_state(), state);
+        nopt.nes_mut().load_state(&state).unwrap();
+        assert_eq!(nopt.nes().save_state(), state);
+
+        // a freshly created machine can load it too
+        let mut nes = Nes::new(test_utils::nrom(&PROGRAM, &[], &[]));
+        nes.load_state(&state).unwrap();
+        assert_eq!(nes.save_state(), state);
+        assert_eq!(nes.cpu.ram, nopt.nes().cpu.ram);
+        assert_eq!(nes.peek(0x6000), nopt.nes().peek(0x6000));
+        assert_ne!(nes.peek(0x6000), 0);
+    }
+
+    #[test]
+    fn mismatched_states_are_rejected() {
+        let mut nes = Nes::new(test_utils::nrom(&PROGRAM, &[], &[]));
+        nes.cpu.ram[0] = 1;
+        let state = nes.save_state();
+        nes.cpu.ram[0] = 2;
+
+        let mut version = state.clone();
+        version[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let mut magic = state.clone();
+        magic[0] = b'?';
+        for (state, error) in [
+            (&state[..state.len() - 1], LoadStateError::SizeMismatch),
+            (&[&state[..], &[0]].concat(), LoadStateError::SizeMismatch),
+            (&state[..2], LoadStateError::SizeMismatch),
+            (&magic, LoadStateError::InvalidHeader),
+            (&version, LoadStateError::UnsupportedVersion(VERSION + 1)),
+        ] {
+            assert_eq!(nes.load_state(state), Err(error));
+            assert_eq!(nes.cpu.ram[0], 2);
+        }
+
+        // a different input device changes the size of the state
+        nes.set_input_device(0, input_device::Zapper::new());
+        assert_eq!(nes.load_state(&state), Err(LoadStateError::SizeMismatch));
+    }
+
+    #[test]
+    fn oam_dma_stalls_cpu() {
+        // SEI; LDA $00 to change the cycle parity or nothing; LDA #$02;
+        // STA $4014; loop: JMP loop
+        let mut stalls = [[0xa5, 0x00].as_slice(), &[]].map(|parity_change| {
+            let program = [
+                &[0x78][..],
+                parity_change,
+                &[0xa9, 0x02, 0x8d, 0x14, 0x40, 0x4c, 0x00, 0x90],
+            ]
+            .concat();
+            let mut nopt = test_utils::nopt(&program);
+            while nopt.nes().cpu.pc != 0x8003 + u16::try_from(parity_change.len()).unwrap() {
+                nopt.step_instruction();
+            }
+            let cycle_count = nopt.nes().cpu.cycle_count;
+            nopt.step_instruction();
+
+            // the DMA starts once the 4 cycles of the write are done
+            let stall = nopt.nes().cpu.cycle_count - cycle_count - 4;
+            assert_eq!(stall, 513 + ((cycle_count + 4) & 1));
+            stall
+        });
+        stalls.sort_unstable();
+        assert_eq!(stalls, [513, 514]);
+    }
+
+    #[test]
+    fn undrained_audio_is_bounded() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        // about 3 seconds of audio at the default sample rate
+        for _ in 0..180 {
+            nopt.run_frame();
         }
+        assert!(nopt.nes().available_audio_samples() < 2 * 48_000);
     }
 }
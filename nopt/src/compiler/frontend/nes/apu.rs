@@ -0,0 +1,318 @@
+mod blip_buffer;
+mod dmc;
+mod envelope;
+mod frame_counter;
+mod length_counter;
+mod noise;
+mod pulse;
+mod triangle;
+
+use crate::compiler::frontend::nes::{
+    Nes,
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+use blip_buffer::BlipBuffer;
+use dmc::Dmc;
+use envelope::Envelope;
+use frame_counter::{FrameClock, FrameCounter};
+use length_counter::LengthCounter;
+use noise::Noise;
+use pulse::Pulse;
+use triangle::Triangle;
+
+const CPU_CLOCK_RATE: u32 = 1_789_773;
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+#[derive(Clone)]
+pub struct Apu {
+    pulse_0: Pulse,
+    pulse_1: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    pub cycle_count: u64,
+    /// The address of the register being accessed by compiled code.
+    register_address: u8,
+    /// The value being written to, or read from, the register being accessed
+    /// by compiled code.
+    register_value: u8,
+    amplitude: f32,
+    blip_buffer: BlipBuffer,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self {
+            pulse_0: Pulse::new(true),
+            pulse_1: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::new(),
+            cycle_count: 0,
+            register_address: 0,
+            register_value: 0,
+            amplitude: 0.0,
+            blip_buffer: BlipBuffer::new(CPU_CLOCK_RATE, DEFAULT_SAMPLE_RATE),
+        }
+    }
+
+    pub(super) fn write_register<
+        Cartridge: crate::cartridge::Cartridge,
+        Visitor: super::Visitor,
+    >(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+        value: Visitor::U8,
+    ) {
+        visitor.call_native(
+            Nes::<Cartridge>::catch_up_before_access,
+            (&raw mut *nes).cast(),
+        );
+
+        // the registers are decoded by a host function rather than by visitor
+        // operations, as catching up already requires a host call and writes
+        // reach deep into the state machines of the channels, which gain
+        // nothing from being compiled
+        let address = visitor.low_byte(address);
+        visitor.set_memory_u8(&raw mut nes.apu.register_address, address);
+        visitor.set_memory_u8(&raw mut nes.apu.register_value, value);
+        visitor.call_native(
+            Self::write_register_from_compiled_code,
+            (&raw mut nes.apu).cast(),
+        );
+    }
+
+    /// Reads $4015, except for bit 5, which is not driven. Like writes, the
+    /// status is assembled by a host function.
+    pub(super) fn read_status<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+    ) -> Visitor::U8 {
+        visitor.call_native(
+            Nes::<Cartridge>::catch_up_before_access,
+            (&raw mut *nes).cast(),
+        );
+
+        visitor.call_native(
+            Self::read_status_from_compiled_code,
+            (&raw mut nes.apu).cast(),
+        );
+        visitor.memory_u8(&raw const nes.apu.register_value)
+    }
+
+    #[expect(clippy::cast_ptr_alignment)]
+    unsafe extern "C" fn write_register_from_compiled_code(apu: *mut u8) {
+        let apu = unsafe { &mut *apu.cast::<Self>() };
+        let value = apu.register_value;
+        let register_index = apu.register_address & 0b11;
+        match apu.register_address {
+            0x00..=0x03 => apu.pulse_0.write_register(register_index, value),
+            0x04..=0x07 => apu.pulse_1.write_register(register_index, value),
+            0x08..=0x0b => apu.triangle.write_register(register_index, value),
+            0x0c..=0x0f => apu.noise.write_register(register_index, value),
+            0x10..=0x13 => apu.dmc.write_register(register_index, value),
+            0x15 => {
+                apu.pulse_0
+                    .length_counter
+                    .set_enabled(value & 0b0000_0001 != 0);
+                apu.pulse_1
+                    .length_counter
+                    .set_enabled(value & 0b0000_0010 != 0);
+                apu.triangle
+                    .length_counter
+                    .set_enabled(value & 0b0000_0100 != 0);
+                apu.noise
+                    .length_counter
+                    .set_enabled(value & 0b0000_1000 != 0);
+                apu.dmc.set_enabled(value & 0b0001_0000 != 0);
+                apu.dmc.is_irq_pending = false;
+            }
+            0x17 => apu.frame_counter.write(value, apu.cycle_count),
+            _ => {}
+        }
+    }
+
+    #[expect(clippy::cast_ptr_alignment)]
+    unsafe extern "C" fn read_status_from_compiled_code(apu: *mut u8) {
+        let apu = unsafe { &mut *apu.cast::<Self>() };
+        apu.register_value = u8::from(apu.pulse_0.length_counter.is_active())
+            | (u8::from(apu.pulse_1.length_counter.is_active()) << 1)
+            | (u8::from(apu.triangle.length_counter.is_active()) << 2)
+            | (u8::from(apu.noise.length_counter.is_active()) << 3)
+            | (u8::from(apu.dmc.is_active()) << 4)
+            | (u8::from(apu.frame_counter.is_irq_pending) << 6)
+            | (u8::from(apu.dmc.is_irq_pending) << 7);
+        apu.frame_counter.is_irq_pending = false;
+    }
+
+    /// Advances the APU by a single CPU cycle.
+    pub(super) fn step(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.cycle_count & 1 != 0 {
+            self.pulse_0.clock_timer();
+            self.pulse_1.clock_timer();
+        }
+
+        match self.frame_counter.step() {
+            Some(FrameClock::QuarterFrame) => self.clock_quarter_frame(),
+            Some(FrameClock::HalfFrame) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            None => {}
+        }
+
+        let amplitude = self.mix();
+        let delta = amplitude - self.amplitude;
+        if delta != 0.0 {
+            self.blip_buffer.add_delta(delta);
+            self.amplitude = amplitude;
+        }
+        self.blip_buffer.clock();
+
+        self.cycle_count += 1;
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_0.clock_quarter_frame();
+        self.pulse_1.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse_0.clock_half_frame();
+        self.pulse_1.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// Combines the channel outputs using the nonlinear mixing formulas of the
+    /// hardware, producing a sample between 0 and 1.
+    fn mix(&self) -> f32 {
+        let pulse = f32::from(self.pulse_0.output() + self.pulse_1.output());
+        let pulse_output = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let triangle = f32::from(self.triangle.output());
+        let noise = f32::from(self.noise.output());
+        let dmc = f32::from(self.dmc.output());
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd_output = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_output + tnd_output
+    }
+
+    /// The address of the next DMC sample byte, if it needs to be fetched.
+    pub(super) fn dmc_dma_address(&self) -> Option<u16> {
+        self.dmc.dma_address()
+    }
+
+    pub(super) fn fill_dmc_sample_buffer(&mut self, value: u8) {
+        self.dmc.fill_sample_buffer(value);
+    }
+
+    pub(super) fn is_irq_asserted(&self) -> bool {
+        self.dmc.is_irq_pending || self.frame_counter.is_irq_pending
+    }
+
+    /// Changes the output sample rate, discarding any samples which have not
+    /// been read yet.
+    pub(super) fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.blip_buffer = BlipBuffer::new(CPU_CLOCK_RATE, sample_rate);
+        self.amplitude = 0.0;
+    }
+
+    pub(super) fn available_samples(&self) -> usize {
+        self.blip_buffer.available_samples()
+    }
+
+    pub(super) fn read_samples(&mut self, samples: &mut [f32]) -> usize {
+        self.blip_buffer.read_samples(samples)
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SaveState for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.pulse_0.save_state(writer);
+        self.pulse_1.save_state(writer);
+        self.triangle.save_state(writer);
+        self.noise.save_state(writer);
+        self.dmc.save_state(writer);
+        self.frame_counter.save_state(writer);
+        self.cycle_count.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.pulse_0.load_state(reader)?;
+        self.pulse_1.load_state(reader)?;
+        self.triangle.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.dmc.load_state(reader)?;
+        self.frame_counter.load_state(reader)?;
+        self.cycle_count.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cartridge::Nrom,
+        compiler::frontend::nes::{Cpu, InterpreterVisitor},
+        test_utils,
+    };
+
+    fn read_status(nes: &mut Nes<Nrom>) -> u8 {
+        Cpu::read(nes, &mut InterpreterVisitor::new(), 0x4015)
+    }
+
+    fn write(nes: &mut Nes<Nrom>, address: u16, value: u8) {
+        Cpu::write(nes, &mut InterpreterVisitor::new(), address, value);
+    }
+
+    #[test]
+    fn status_reports_active_channels() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        write(&mut nes, 0x4015, 0b0000_1111);
+        write(&mut nes, 0x4003, 0b0000_1000);
+        write(&mut nes, 0x400b, 0b0000_1000);
+        // bit 5 is open bus, holding bit 5 of the address's high byte
+        assert_eq!(read_status(&mut nes), 0b0000_0101);
+
+        write(&mut nes, 0x4015, 0b0000_0100);
+        assert_eq!(read_status(&mut nes), 0b0000_0100);
+    }
+
+    #[test]
+    fn reading_status_clears_frame_interrupt_only() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.apu.frame_counter.is_irq_pending = true;
+        nes.apu.dmc.is_irq_pending = true;
+        assert_eq!(read_status(&mut nes), 0b1100_0000);
+        assert_eq!(read_status(&mut nes), 0b1000_0000);
+
+        // writing the status clears the DMC interrupt instead
+        write(&mut nes, 0x4015, 0);
+        assert_eq!(read_status(&mut nes), 0);
+    }
+}
@@ -0,0 +1,205 @@
+use std::f64::consts::PI;
+
+/// The number of fractional positions at which steps can be inserted.
+const PHASE_COUNT: usize = 64;
+/// The number of output samples affected by each step.
+const KERNEL_WIDTH: usize = 16;
+/// The number of output samples kept for reading, beyond which the oldest
+/// half is dropped, so that samples don't pile up if they are never read.
+const MAX_AVAILABLE_SAMPLES: usize = 1 << 16;
+
+/// Converts a signal sampled at the CPU clock rate into one sampled at a
+/// host rate without aliasing, by inserting each change in amplitude as a
+/// band-limited step.
+#[derive(Clone)]
+pub(super) struct BlipBuffer {
+    /// The number of output samples per input clock.
+    samples_per_clock: f64,
+    /// The position of the current input clock, in output samples from the
+    /// start of the buffer.
+    time: f64,
+    kernels: Box<[[f32; KERNEL_WIDTH]; PHASE_COUNT]>,
+    /// The amplitude changes of each output sample, yet to be integrated.
+    deltas: Vec<f32>,
+    integrator: f32,
+    high_pass_factor: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl BlipBuffer {
+    #[expect(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub(super) fn new(clock_rate: u32, sample_rate: u32) -> Self {
+        let sample_rate = f64::from(sample_rate);
+
+        // an impulse response per phase, made of a windowed sinc which cuts
+        // off slightly below the Nyquist frequency of the output
+        let mut kernels = Box::new([[0.0; KERNEL_WIDTH]; PHASE_COUNT]);
+        let cutoff = 0.45;
+        for (phase, kernel) in kernels.iter_mut().enumerate() {
+            let phase_offset = phase as f64 / PHASE_COUNT as f64;
+            let mut taps = [0.0; KERNEL_WIDTH];
+            for (tap_index, tap) in taps.iter_mut().enumerate() {
+                let x = tap_index as f64 - (KERNEL_WIDTH / 2) as f64 - phase_offset;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (2.0 * PI * cutoff * x).sin() / (2.0 * PI * cutoff * x)
+                };
+                let window_position = (x / KERNEL_WIDTH as f64 + 0.5).clamp(0.0, 1.0);
+                let window = 0.42 - 0.5 * (2.0 * PI * window_position).cos()
+                    + 0.08 * (4.0 * PI * window_position).cos();
+                *tap = sinc * window;
+            }
+            let sum = taps.iter().sum::<f64>();
+            for (kernel_tap, tap) in kernel.iter_mut().zip(taps) {
+                *kernel_tap = (tap / sum) as f32;
+            }
+        }
+
+        // a DC-blocking high-pass filter at around 90 Hz, like the one in the
+        // console's output stage
+        let high_pass_factor = (-2.0 * PI * 90.0 / sample_rate).exp() as f32;
+
+        Self {
+            samples_per_clock: sample_rate / f64::from(clock_rate),
+            time: 0.0,
+            kernels,
+            deltas: vec![0.0; KERNEL_WIDTH],
+            integrator: 0.0,
+            high_pass_factor,
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    /// Inserts a change in amplitude at the current input clock.
+    #[expect(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub(super) fn add_delta(&mut self, delta: f32) {
+        let sample_index = self.time as usize;
+        let phase = ((self.time.fract() * PHASE_COUNT as f64) as usize).min(PHASE_COUNT - 1);
+
+        let required_len = sample_index + KERNEL_WIDTH;
+        if self.deltas.len() < required_len {
+            self.deltas.resize(required_len, 0.0);
+        }
+
+        let kernel = &self.kernels[phase];
+        for (sample_delta, tap) in self.deltas[sample_index..].iter_mut().zip(kernel) {
+            *sample_delta += delta * tap;
+        }
+    }
+
+    pub(super) fn clock(&mut self) {
+        self.time += self.samples_per_clock;
+        if self.available_samples() > MAX_AVAILABLE_SAMPLES {
+            self.take_samples(MAX_AVAILABLE_SAMPLES / 2, |_, _| {});
+        }
+    }
+
+    /// The number of output samples which are no longer affected by future
+    /// input.
+    #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub(super) fn available_samples(&self) -> usize {
+        self.time as usize
+    }
+
+    pub(super) fn read_samples(&mut self, samples: &mut [f32]) -> usize {
+        let count = samples.len().min(self.available_samples());
+        self.take_samples(count, |index, sample| samples[index] = sample);
+        count
+    }
+
+    /// Removes the oldest output samples, passing each one along with its
+    /// index to the given function.
+    #[expect(clippy::cast_precision_loss)]
+    fn take_samples(&mut self, count: usize, mut output: impl FnMut(usize, f32)) {
+        if self.deltas.len() < count {
+            self.deltas.resize(count, 0.0);
+        }
+
+        // dropped samples are still integrated and filtered, so that the
+        // following ones keep their level
+        for (index, delta) in self.deltas.drain(..count).enumerate() {
+            self.integrator += delta;
+            let sample = self.integrator - self.previous_input
+                + self.high_pass_factor * self.previous_output;
+            self.previous_input = self.integrator;
+            self.previous_output = sample;
+            output(index, sample);
+        }
+
+        self.time -= count as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLOCK_RATE: u32 = 1_789_773;
+
+    #[test]
+    fn produces_samples_at_sample_rate() {
+        let mut blip_buffer = BlipBuffer::new(CLOCK_RATE, 48_000);
+        for _ in 0..CLOCK_RATE {
+            blip_buffer.clock();
+        }
+        assert!((47_999..=48_000).contains(&blip_buffer.available_samples()));
+
+        let mut samples = vec![0.0; 1000];
+        assert_eq!(blip_buffer.read_samples(&mut samples), 1000);
+        assert!(samples.iter().all(|sample| *sample == 0.0));
+        assert!((46_999..=47_000).contains(&blip_buffer.available_samples()));
+    }
+
+    #[test]
+    fn step_is_band_limited_and_decays() {
+        let mut blip_buffer = BlipBuffer::new(CLOCK_RATE, 48_000);
+        for _ in 0..CLOCK_RATE / 100 {
+            blip_buffer.clock();
+        }
+        blip_buffer.add_delta(1.0);
+        for _ in 0..CLOCK_RATE {
+            blip_buffer.clock();
+        }
+
+        let mut samples = vec![0.0; 48_000];
+        blip_buffer.read_samples(&mut samples);
+        // the step is spread over a few samples, rather than being
+        // instantaneous
+        let step_start = samples.iter().position(|sample| *sample != 0.0).unwrap();
+        assert!(samples[step_start] < 0.5);
+        let peak = samples.iter().copied().fold(0.0, f32::max);
+        assert!((0.95..1.1).contains(&peak), "peak: {peak}");
+        // the constant offset is then removed by the high-pass filter
+        assert!(samples.last().unwrap().abs() < 0.01);
+    }
+
+    #[test]
+    fn unread_samples_are_bounded() {
+        let mut blip_buffer = BlipBuffer::new(CLOCK_RATE, 48_000);
+        for clock in 0..CLOCK_RATE * 5 {
+            if clock % 1000 == 0 {
+                blip_buffer.add_delta(if clock % 2000 == 0 { 1.0 } else { -1.0 });
+            }
+            blip_buffer.clock();
+            assert!(blip_buffer.available_samples() <= MAX_AVAILABLE_SAMPLES);
+        }
+        assert!(blip_buffer.deltas.len() <= MAX_AVAILABLE_SAMPLES + KERNEL_WIDTH);
+        assert!(blip_buffer.available_samples() >= MAX_AVAILABLE_SAMPLES / 2);
+
+        // the newest samples are kept, still oscillating around zero
+        let mut samples = vec![0.0; 48_000];
+        let count = blip_buffer.read_samples(&mut samples);
+        assert!(
+            samples[..count]
+                .iter()
+                .all(|sample| (-1.1..1.1).contains(sample))
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::compiler::frontend::nes::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter,
+};
+
+const TIMER_PERIODS: [u16; 0x10] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+#[derive(Clone)]
+#[expect(clippy::struct_excessive_bools)]
+pub(super) struct Dmc {
+    is_irq_enabled: bool,
+    is_looping: bool,
+    timer: u16,
+    timer_period: u16,
+    output_level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift_register: u8,
+    bits_remaining: u8,
+    is_silenced: bool,
+    pub(super) is_irq_pending: bool,
+}
+
+impl Dmc {
+    pub(super) fn new() -> Self {
+        Self {
+            is_irq_enabled: false,
+            is_looping: false,
+            timer: 0,
+            timer_period: TIMER_PERIODS[0],
+            output_level: 0,
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            is_silenced: true,
+            is_irq_pending: false,
+        }
+    }
+
+    pub(super) fn write_register(&mut self, register_index: u8, value: u8) {
+        match register_index {
+            0 => {
+                self.is_irq_enabled = value & 0b1000_0000 != 0;
+                if !self.is_irq_enabled {
+                    self.is_irq_pending = false;
+                }
+                self.is_looping = value & 0b0100_0000 != 0;
+                self.timer_period = TIMER_PERIODS[usize::from(value & 0b1111)];
+            }
+            1 => self.output_level = value & 0b0111_1111,
+            2 => self.sample_address = 0xc000 | (u16::from(value) << 6),
+            3 => self.sample_length = (u16::from(value) << 4) | 1,
+            _ => unreachable!(),
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, is_enabled: bool) {
+        if !is_enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    /// The address of the next sample byte, if the sample buffer needs to be
+    /// refilled by a DMA transfer.
+    pub(super) fn dma_address(&self) -> Option<u16> {
+        (self.sample_buffer.is_none() && self.bytes_remaining > 0).then_some(self.current_address)
+    }
+
+    pub(super) fn fill_sample_buffer(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.is_looping {
+                self.restart();
+            } else if self.is_irq_enabled {
+                self.is_irq_pending = true;
+            }
+        }
+    }
+
+    /// Clocks the timer, which happens on every CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.is_silenced {
+            if self.shift_register & 1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.is_silenced = false;
+                    self.shift_register = sample;
+                }
+                None => self.is_silenced = true,
+            }
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+impl SaveState for Dmc {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.is_irq_enabled.save_state(writer);
+        self.is_looping.save_state(writer);
+        self.timer.save_state(writer);
+        self.timer_period.save_state(writer);
+        self.output_level.save_state(writer);
+        self.sample_address.save_state(writer);
+        self.sample_length.save_state(writer);
+        self.current_address.save_state(writer);
+        self.bytes_remaining.save_state(writer);
+        self.sample_buffer.save_state(writer);
+        self.shift_register.save_state(writer);
+        self.bits_remaining.save_state(writer);
+        self.is_silenced.save_state(writer);
+        self.is_irq_pending.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.is_irq_enabled.load_state(reader)?;
+        self.is_looping.load_state(reader)?;
+        self.timer.load_state(reader)?;
+        self.timer_period.load_state(reader)?;
+        self.output_level.load_state(reader)?;
+        self.sample_address.load_state(reader)?;
+        self.sample_length.load_state(reader)?;
+        self.current_address.load_state(reader)?;
+        self.bytes_remaining.load_state(reader)?;
+        self.sample_buffer.load_state(reader)?;
+        self.shift_register.load_state(reader)?;
+        self.bits_remaining.load_state(reader)?;
+        self.is_silenced.load_state(reader)?;
+        self.is_irq_pending.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_bytes_are_fetched_from_sample_address() {
+        let mut dmc = Dmc::new();
+        dmc.write_register(2, 0xff);
+        dmc.write_register(3, 0);
+        assert_eq!(dmc.dma_address(), None);
+
+        dmc.set_enabled(true);
+        assert_eq!(dmc.dma_address(), Some(0xffc0));
+        dmc.fill_sample_buffer(0);
+        // the sample buffer is still full
+        assert_eq!(dmc.dma_address(), None);
+        assert!(!dmc.is_active());
+    }
+
+    #[test]
+    fn sample_address_wraps_to_0x8000() {
+        let mut dmc = Dmc::new();
+        dmc.write_register(2, 0xff);
+        dmc.write_register(3, 0x04);
+        dmc.set_enabled(true);
+        dmc.current_address = 0xffff;
+        dmc.fill_sample_buffer(0);
+        dmc.sample_buffer = None;
+        assert_eq!(dmc.dma_address(), Some(0x8000));
+    }
+
+    #[test]
+    fn irq_is_raised_at_end_of_sample_unless_looping() {
+        for (control, is_irq_pending, is_active) in [
+            (0b0000_0000, false, false),
+            (0b1000_0000, true, false),
+            (0b1100_0000, false, true),
+        ] {
+            let mut dmc = Dmc::new();
+            dmc.write_register(0, control);
+            dmc.set_enabled(true);
+            dmc.fill_sample_buffer(0);
+            assert_eq!(dmc.is_irq_pending, is_irq_pending);
+            assert_eq!(dmc.is_active(), is_active);
+        }
+    }
+
+    #[test]
+    fn output_level_follows_sample_bits() {
+        let mut dmc = Dmc::new();
+        // the fastest rate, clocking the output every 54 CPU cycles
+        dmc.write_register(0, 0x0f);
+        dmc.write_register(1, 0x40);
+        dmc.set_enabled(true);
+        dmc.fill_sample_buffer(0b0000_1011);
+
+        // the sample is only played once the current output cycle has ended
+        let mut output_levels = Vec::new();
+        for _ in 0..16 {
+            for _ in 0..54 {
+                dmc.clock_timer();
+            }
+            output_levels.push(dmc.output());
+        }
+        assert_eq!(
+            output_levels[8..],
+            [0x42, 0x44, 0x42, 0x44, 0x42, 0x40, 0x3e, 0x3c]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use crate::compiler::frontend::nes::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter,
+};
+
+#[derive(Clone, Default)]
+pub(super) struct Envelope {
+    is_start_flag_set: bool,
+    is_looping: bool,
+    is_volume_constant: bool,
+    /// Either the constant volume or the period of the divider.
+    parameter: u8,
+    divider: u8,
+    decay_level: u8,
+}
+
+impl Envelope {
+    pub(super) fn write_control(&mut self, value: u8) {
+        self.is_looping = value & 0b0010_0000 != 0;
+        self.is_volume_constant = value & 0b0001_0000 != 0;
+        self.parameter = value & 0b0000_1111;
+    }
+
+    pub(super) fn restart(&mut self) {
+        self.is_start_flag_set = true;
+    }
+
+    pub(super) fn clock(&mut self) {
+        if self.is_start_flag_set {
+            self.is_start_flag_set = false;
+            self.decay_level = 15;
+            self.divider = self.parameter;
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+
+        self.divider = self.parameter;
+        if self.decay_level > 0 {
+            self.decay_level -= 1;
+        } else if self.is_looping {
+            self.decay_level = 15;
+        }
+    }
+
+    pub(super) fn volume(&self) -> u8 {
+        if self.is_volume_constant {
+            self.parameter
+        } else {
+            self.decay_level
+        }
+    }
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.is_start_flag_set.save_state(writer);
+        self.is_looping.save_state(writer);
+        self.is_volume_constant.save_state(writer);
+        self.parameter.save_state(writer);
+        self.divider.save_state(writer);
+        self.decay_level.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.is_start_flag_set.load_state(reader)?;
+        self.is_looping.load_state(reader)?;
+        self.is_volume_constant.load_state(reader)?;
+        self.parameter.load_state(reader)?;
+        self.divider.load_state(reader)?;
+        self.decay_level.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_volume() {
+        let mut envelope = Envelope::default();
+        envelope.write_control(0b0001_0111);
+        envelope.restart();
+        for _ in 0..20 {
+            envelope.clock();
+            assert_eq!(envelope.volume(), 7);
+        }
+    }
+
+    #[test]
+    fn decays_once_per_divider_period() {
+        let mut envelope = Envelope::default();
+        // a divider period of 2 clocks
+        envelope.write_control(0b0000_0001);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(envelope.volume(), 15);
+
+        let volumes: Vec<_> = (0..6)
+            .map(|_| {
+                envelope.clock();
+                envelope.volume()
+            })
+            .collect();
+        assert_eq!(volumes, [15, 14, 14, 13, 13, 12]);
+    }
+
+    #[test]
+    fn decay_stops_at_zero_unless_looping() {
+        for (control, final_volume) in [(0b0000_0000, 0), (0b0010_0000, 15)] {
+            let mut envelope = Envelope::default();
+            envelope.write_control(control);
+            envelope.restart();
+            for _ in 0..=16 {
+                envelope.clock();
+            }
+            assert_eq!(envelope.volume(), final_volume);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::compiler::frontend::nes::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum FrameClock {
+    /// Clocks the envelopes and the triangle linear counter.
+    QuarterFrame,
+    /// Clocks the length counters and sweep units, in addition to everything
+    /// clocked by a quarter frame.
+    HalfFrame,
+}
+
+#[derive(Clone)]
+pub(super) struct FrameCounter {
+    cycle: u16,
+    is_five_step_mode: bool,
+    is_irq_inhibited: bool,
+    pub(super) is_irq_pending: bool,
+    /// The value written to $4017 which is yet to reset the sequencer.
+    pending_value: Option<u8>,
+    pending_value_delay: u8,
+}
+
+impl FrameCounter {
+    pub(super) fn new() -> Self {
+        Self {
+            cycle: 0,
+            is_five_step_mode: false,
+            is_irq_inhibited: false,
+            is_irq_pending: false,
+            pending_value: None,
+            pending_value_delay: 0,
+        }
+    }
+
+    pub(super) fn write(&mut self, value: u8, cpu_cycle: u64) {
+        self.is_irq_inhibited = value & 0b0100_0000 != 0;
+        if self.is_irq_inhibited {
+            self.is_irq_pending = false;
+        }
+
+        // the sequencer is only reset once the current APU cycle has finished
+        self.pending_value = Some(value);
+        self.pending_value_delay = if cpu_cycle & 1 != 0 { 4 } else { 3 };
+    }
+
+    /// Advances the sequencer by a single CPU cycle.
+    pub(super) fn step(&mut self) -> Option<FrameClock> {
+        if let Some(value) = self.pending_value {
+            self.pending_value_delay -= 1;
+            if self.pending_value_delay == 0 {
+                self.pending_value = None;
+                self.cycle = 0;
+                self.is_five_step_mode = value & 0b1000_0000 != 0;
+                if self.is_five_step_mode {
+                    return Some(FrameClock::HalfFrame);
+                }
+                return None;
+            }
+        }
+
+        self.cycle += 1;
+        match (self.cycle, self.is_five_step_mode) {
+            (7457 | 22371, _) => Some(FrameClock::QuarterFrame),
+            (14913, _) | (29829, false) | (37281, true) => {
+                if self.cycle == 29829 {
+                    self.set_irq_pending();
+                }
+                Some(FrameClock::HalfFrame)
+            }
+            (29828, false) => {
+                self.set_irq_pending();
+                None
+            }
+            (29830, false) => {
+                self.set_irq_pending();
+                self.cycle = 0;
+                None
+            }
+            (37282, true) => {
+                self.cycle = 0;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn set_irq_pending(&mut self) {
+        if !self.is_irq_inhibited {
+            self.is_irq_pending = true;
+        }
+    }
+}
+
+impl SaveState for FrameCounter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.cycle.save_state(writer);
+        self.is_five_step_mode.save_state(writer);
+        self.is_irq_inhibited.save_state(writer);
+        self.is_irq_pending.save_state(writer);
+        self.pending_value.save_state(writer);
+        self.pending_value_delay.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.cycle.load_state(reader)?;
+        self.is_five_step_mode.load_state(reader)?;
+        self.is_irq_inhibited.load_state(reader)?;
+        self.is_irq_pending.load_state(reader)?;
+        self.pending_value.load_state(reader)?;
+        self.pending_value_delay.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steps the frame counter over the given number of CPU cycles, returning
+    /// the cycles (counting from 1) on which it clocked the other units.
+    fn clocks(frame_counter: &mut FrameCounter, cycles: u32) -> Vec<(u32, FrameClock)> {
+        (1..=cycles)
+            .filter_map(|cycle| Some((cycle, frame_counter.step()?)))
+            .collect()
+    }
+
+    #[test]
+    fn four_step_sequence() {
+        let mut frame_counter = FrameCounter::new();
+        assert_eq!(
+            clocks(&mut frame_counter, 29830),
+            [
+                (7457, FrameClock::QuarterFrame),
+                (14913, FrameClock::HalfFrame),
+                (22371, FrameClock::QuarterFrame),
+                (29829, FrameClock::HalfFrame),
+            ]
+        );
+        assert_eq!(
+            clocks(&mut frame_counter, 7457),
+            [(7457, FrameClock::QuarterFrame)]
+        );
+    }
+
+    #[test]
+    fn five_step_sequence() {
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.write(0b1000_0000, 0);
+        assert_eq!(
+            clocks(&mut frame_counter, 37284),
+            [
+                // writing with bit 7 set immediately clocks a half frame
+                (3, FrameClock::HalfFrame),
+                (3 + 7457, FrameClock::QuarterFrame),
+                (3 + 14913, FrameClock::HalfFrame),
+                (3 + 22371, FrameClock::QuarterFrame),
+                (3 + 37281, FrameClock::HalfFrame),
+            ]
+        );
+        assert!(!frame_counter.is_irq_pending);
+    }
+
+    #[test]
+    fn write_takes_effect_after_current_apu_cycle() {
+        for (cpu_cycle, delay) in [(0, 3), (1, 4)] {
+            let mut frame_counter = FrameCounter::new();
+            frame_counter.write(0b1000_0000, cpu_cycle);
+            assert_eq!(
+                clocks(&mut frame_counter, 4),
+                [(delay, FrameClock::HalfFrame)]
+            );
+        }
+    }
+
+    #[test]
+    fn irq_is_raised_at_end_of_four_step_sequence() {
+        let mut frame_counter = FrameCounter::new();
+        clocks(&mut frame_counter, 29827);
+        assert!(!frame_counter.is_irq_pending);
+        frame_counter.step();
+        assert!(frame_counter.is_irq_pending);
+
+        // the flag is set again on the following two cycles
+        frame_counter.is_irq_pending = false;
+        frame_counter.step();
+        assert!(frame_counter.is_irq_pending);
+        frame_counter.is_irq_pending = false;
+        frame_counter.step();
+        assert!(frame_counter.is_irq_pending);
+        frame_counter.is_irq_pending = false;
+        frame_counter.step();
+        assert!(!frame_counter.is_irq_pending);
+    }
+
+    #[test]
+    fn irq_is_not_raised_while_inhibited() {
+        let mut frame_counter = FrameCounter::new();
+        clocks(&mut frame_counter, 29830);
+        assert!(frame_counter.is_irq_pending);
+
+        // inhibiting also clears the flag
+        frame_counter.write(0b0100_0000, 0);
+        assert!(!frame_counter.is_irq_pending);
+        clocks(&mut frame_counter, 29830 * 2);
+        assert!(!frame_counter.is_irq_pending);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::compiler::frontend::nes::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter,
+};
+
+const LENGTHS: [u8; 0x20] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Clone, Default)]
+pub(super) struct LengthCounter {
+    counter: u8,
+    is_enabled: bool,
+    is_halted: bool,
+}
+
+impl LengthCounter {
+    pub(super) fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+        if !is_enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub(super) fn set_halted(&mut self, is_halted: bool) {
+        self.is_halted = is_halted;
+    }
+
+    pub(super) fn load(&mut self, length_index: u8) {
+        if self.is_enabled {
+            self.counter = LENGTHS[usize::from(length_index)];
+        }
+    }
+
+    pub(super) fn clock(&mut self) {
+        if !self.is_halted && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub(super) fn is_active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+impl SaveState for LengthCounter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.counter.save_state(writer);
+        self.is_enabled.save_state(writer);
+        self.is_halted.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.counter.load_state(reader)?;
+        self.is_enabled.load_state(reader)?;
+        self.is_halted.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_down_to_zero() {
+        let mut length_counter = LengthCounter::default();
+        length_counter.set_enabled(true);
+        // index 3 loads a length of 2
+        length_counter.load(3);
+        assert!(length_counter.is_active());
+        length_counter.clock();
+        assert!(length_counter.is_active());
+        length_counter.clock();
+        assert!(!length_counter.is_active());
+        length_counter.clock();
+        assert!(!length_counter.is_active());
+    }
+
+    #[test]
+    fn is_not_loaded_while_disabled() {
+        let mut length_counter = LengthCounter::default();
+        length_counter.load(0);
+        assert!(!length_counter.is_active());
+    }
+
+    #[test]
+    fn disabling_clears_counter() {
+        let mut length_counter = LengthCounter::default();
+        length_counter.set_enabled(true);
+        length_counter.load(0);
+        length_counter.set_enabled(false);
+        assert!(!length_counter.is_active());
+    }
+
+    #[test]
+    fn is_not_clocked_while_halted() {
+        let mut length_counter = LengthCounter::default();
+        length_counter.set_enabled(true);
+        length_counter.set_halted(true);
+        length_counter.load(3);
+        for _ in 0..4 {
+            length_counter.clock();
+        }
+        assert!(length_counter.is_active());
+    }
+}
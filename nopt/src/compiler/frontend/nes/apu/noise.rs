@@ -0,0 +1,101 @@
+use crate::compiler::frontend::nes::{
+    apu::{Envelope, LengthCounter},
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+
+const TIMER_PERIODS: [u16; 0x10] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+#[derive(Clone)]
+pub(super) struct Noise {
+    /// Whether the feedback is taken from bit 6 rather than bit 1 of the
+    /// shift register, producing a shorter sequence.
+    is_mode_short: bool,
+    shift_register: u16,
+    timer: u16,
+    timer_period: u16,
+    envelope: Envelope,
+    pub(super) length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub(super) fn new() -> Self {
+        Self {
+            is_mode_short: false,
+            shift_register: 1,
+            timer: 0,
+            timer_period: TIMER_PERIODS[0],
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    pub(super) fn write_register(&mut self, register_index: u8, value: u8) {
+        match register_index {
+            0 => {
+                self.length_counter.set_halted(value & 0b0010_0000 != 0);
+                self.envelope.write_control(value);
+            }
+            1 => {}
+            2 => {
+                self.is_mode_short = value & 0b1000_0000 != 0;
+                self.timer_period = TIMER_PERIODS[usize::from(value & 0b1111)];
+            }
+            3 => {
+                self.length_counter.load(value >> 3);
+                self.envelope.restart();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocks the timer, which happens on every CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period - 1;
+        let feedback_bit_index = if self.is_mode_short { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> feedback_bit_index)) & 1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if !self.length_counter.is_active() || self.shift_register & 1 != 0 {
+            return 0;
+        }
+        self.envelope.volume()
+    }
+}
+
+impl SaveState for Noise {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.is_mode_short.save_state(writer);
+        self.shift_register.save_state(writer);
+        self.timer.save_state(writer);
+        self.timer_period.save_state(writer);
+        self.envelope.save_state(writer);
+        self.length_counter.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.is_mode_short.load_state(reader)?;
+        self.shift_register.load_state(reader)?;
+        self.timer.load_state(reader)?;
+        self.timer_period.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.length_counter.load_state(reader)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use crate::compiler::frontend::nes::{
+    apu::{Envelope, LengthCounter},
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+
+const DUTY_CYCLE_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Clone, Default)]
+struct Sweep {
+    is_enabled: bool,
+    period: u8,
+    is_negated: bool,
+    shift: u8,
+    divider: u8,
+    is_reload_flag_set: bool,
+}
+
+#[derive(Clone, Default)]
+pub(super) struct Pulse {
+    /// Whether the sweep unit negates using ones' complement, which is only
+    /// the case for the first pulse channel.
+    is_negation_ones_complement: bool,
+    duty_cycle: u8,
+    sequence_step: u8,
+    timer: u16,
+    timer_period: u16,
+    sweep: Sweep,
+    envelope: Envelope,
+    pub(super) length_counter: LengthCounter,
+}
+
+impl Pulse {
+    pub(super) fn new(is_negation_ones_complement: bool) -> Self {
+        Self {
+            is_negation_ones_complement,
+            ..Self::default()
+        }
+    }
+
+    pub(super) fn write_register(&mut self, register_index: u8, value: u8) {
+        match register_index {
+            0 => {
+                self.duty_cycle = value >> 6;
+                self.length_counter.set_halted(value & 0b0010_0000 != 0);
+                self.envelope.write_control(value);
+            }
+            1 => {
+                self.sweep = Sweep {
+                    is_enabled: value & 0b1000_0000 != 0,
+                    period: (value >> 4) & 0b111,
+                    is_negated: value & 0b0000_1000 != 0,
+                    shift: value & 0b111,
+                    divider: self.sweep.divider,
+                    is_reload_flag_set: true,
+                };
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | u16::from(value),
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | (u16::from(value & 0b111) << 8);
+                self.length_counter.load(value >> 3);
+                self.envelope.restart();
+                self.sequence_step = 0;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocks the timer, which happens on every other CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        } else {
+            self.timer = self.timer_period;
+            self.sequence_step = (self.sequence_step + 1) % 8;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+
+        if self.sweep.divider == 0
+            && self.sweep.is_enabled
+            && self.sweep.shift > 0
+            && !self.is_muted_by_sweep()
+        {
+            self.timer_period = self.sweep_target_period();
+        }
+        if self.sweep.divider == 0 || self.sweep.is_reload_flag_set {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.is_reload_flag_set = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    fn sweep_target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if self.sweep.is_negated {
+            let change = change + u16::from(self.is_negation_ones_complement);
+            self.timer_period.saturating_sub(change)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    fn is_muted_by_sweep(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target_period() > 0x7ff
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        let sequence = DUTY_CYCLE_SEQUENCES[usize::from(self.duty_cycle)];
+        if !self.length_counter.is_active()
+            || self.is_muted_by_sweep()
+            || sequence[usize::from(self.sequence_step)] == 0
+        {
+            return 0;
+        }
+        self.envelope.volume()
+    }
+}
+
+impl SaveState for Sweep {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.is_enabled.save_state(writer);
+        self.period.save_state(writer);
+        self.is_negated.save_state(writer);
+        self.shift.save_state(writer);
+        self.divider.save_state(writer);
+        self.is_reload_flag_set.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.is_enabled.load_state(reader)?;
+        self.period.load_state(reader)?;
+        self.is_negated.load_state(reader)?;
+        self.shift.load_state(reader)?;
+        self.divider.load_state(reader)?;
+        self.is_reload_flag_set.load_state(reader)?;
+        Ok(())
+    }
+}
+
+impl SaveState for Pulse {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.is_negation_ones_complement.save_state(writer);
+        self.duty_cycle.save_state(writer);
+        self.sequence_step.save_state(writer);
+        self.timer.save_state(writer);
+        self.timer_period.save_state(writer);
+        self.sweep.save_state(writer);
+        self.envelope.save_state(writer);
+        self.length_counter.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.is_negation_ones_complement.load_state(reader)?;
+        self.duty_cycle.load_state(reader)?;
+        self.sequence_step.load_state(reader)?;
+        self.timer.load_state(reader)?;
+        self.timer_period.load_state(reader)?;
+        self.sweep.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.length_counter.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pulse(is_negation_ones_complement: bool, timer_period: u16, sweep: u8) -> Pulse {
+        let mut pulse = Pulse::new(is_negation_ones_complement);
+        pulse.length_counter.set_enabled(true);
+        // duty cycle 2 with a constant volume of 15
+        pulse.write_register(0, 0b1001_1111);
+        pulse.write_register(1, sweep);
+        pulse.write_register(2, timer_period.to_le_bytes()[0]);
+        pulse.write_register(3, timer_period.to_le_bytes()[1]);
+        pulse
+    }
+
+    fn outputs(pulse: &mut Pulse) -> Vec<u8> {
+        (0..8)
+            .map(|_| {
+                for _ in 0..=pulse.timer_period {
+                    pulse.clock_timer();
+                }
+                pulse.output()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn outputs_duty_cycle_sequence() {
+        let mut pulse = pulse(false, 0x100, 0);
+        assert_eq!(outputs(&mut pulse), [15, 15, 15, 15, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn is_muted_by_low_timer_period() {
+        let mut pulse = pulse(false, 7, 0);
+        assert!(outputs(&mut pulse).iter().all(|output| *output == 0));
+    }
+
+    #[test]
+    fn is_muted_by_sweep_target_overflow() {
+        // the target period of 0x600 + (0x600 >> 1) exceeds 0x7ff, even with
+        // the sweep unit disabled
+        let mut muted_pulse = pulse(false, 0x600, 0b0000_0001);
+        assert!(outputs(&mut muted_pulse).iter().all(|output| *output == 0));
+
+        let mut unmuted_pulse = pulse(false, 0x600, 0b0000_0010);
+        assert!(
+            outputs(&mut unmuted_pulse)
+                .iter()
+                .any(|output| *output != 0)
+        );
+    }
+
+    #[test]
+    fn sweep_adjusts_timer_period_at_divider_period() {
+        for (is_negation_ones_complement, sweep, timer_period) in [
+            (false, 0b1000_0001, 0x180),
+            (false, 0b1000_1001, 0x80),
+            (true, 0b1000_1001, 0x7f),
+        ] {
+            let mut pulse = pulse(is_negation_ones_complement, 0x100, sweep);
+            pulse.clock_half_frame();
+            assert_eq!(pulse.timer_period, timer_period);
+        }
+
+        // a divider period of 2 half frames
+        let mut pulse = pulse(false, 0x100, 0b1001_0001);
+        let timer_periods: Vec<_> = (0..4)
+            .map(|_| {
+                pulse.clock_half_frame();
+                pulse.timer_period
+            })
+            .collect();
+        assert_eq!(timer_periods, [0x180, 0x180, 0x240, 0x240]);
+    }
+}
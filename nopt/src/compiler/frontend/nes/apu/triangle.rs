@@ -0,0 +1,99 @@
+use crate::compiler::frontend::nes::{
+    apu::LengthCounter,
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Clone, Default)]
+pub(super) struct Triangle {
+    /// Doubles as the length counter halt flag.
+    is_control_flag_set: bool,
+    linear_counter_period: u8,
+    linear_counter: u8,
+    is_linear_counter_reload_flag_set: bool,
+    sequence_step: u8,
+    timer: u16,
+    timer_period: u16,
+    pub(super) length_counter: LengthCounter,
+}
+
+impl Triangle {
+    pub(super) fn write_register(&mut self, register_index: u8, value: u8) {
+        match register_index {
+            0 => {
+                self.is_control_flag_set = value & 0b1000_0000 != 0;
+                self.length_counter.set_halted(self.is_control_flag_set);
+                self.linear_counter_period = value & 0b0111_1111;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x0700) | u16::from(value),
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | (u16::from(value & 0b111) << 8);
+                self.length_counter.load(value >> 3);
+                self.is_linear_counter_reload_flag_set = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Clocks the timer, which happens on every CPU cycle.
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period;
+        if self.linear_counter > 0 && self.length_counter.is_active() {
+            self.sequence_step = (self.sequence_step + 1) % 32;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        if self.is_linear_counter_reload_flag_set {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.is_control_flag_set {
+            self.is_linear_counter_reload_flag_set = false;
+        }
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        SEQUENCE[usize::from(self.sequence_step)]
+    }
+}
+
+impl SaveState for Triangle {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.is_control_flag_set.save_state(writer);
+        self.linear_counter_period.save_state(writer);
+        self.linear_counter.save_state(writer);
+        self.is_linear_counter_reload_flag_set.save_state(writer);
+        self.sequence_step.save_state(writer);
+        self.timer.save_state(writer);
+        self.timer_period.save_state(writer);
+        self.length_counter.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.is_control_flag_set.load_state(reader)?;
+        self.linear_counter_period.load_state(reader)?;
+        self.linear_counter.load_state(reader)?;
+        self.is_linear_counter_reload_flag_set.load_state(reader)?;
+        self.sequence_step.load_state(reader)?;
+        self.timer.load_state(reader)?;
+        self.timer_period.load_state(reader)?;
+        self.length_counter.load_state(reader)?;
+        Ok(())
+    }
+}
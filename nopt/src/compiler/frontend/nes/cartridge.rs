@@ -1,20 +1,41 @@
 mod nrom;
 
+use crate::compiler::frontend::nes::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter,
+};
 pub use nrom::Nrom;
 
 #[must_use]
 pub fn from_bytes_with_header(bytes: &[u8]) -> AnyCartridge {
+    from_bytes_with_header_and_battery_ram(bytes, &[])
+}
+
+/// Like [`from_bytes_with_header`], but also restores the contents of the
+/// battery-backed RAM, if the header declares any. Contents shorter than the
+/// RAM (e.g. when no save exists yet) are padded with zeros.
+#[must_use]
+pub fn from_bytes_with_header_and_battery_ram(bytes: &[u8], battery_ram: &[u8]) -> AnyCartridge {
     let (header_bytes, rom_bytes) = bytes.split_at(0x10);
 
     let prg_rom_chunks = header_bytes[4];
-    let (prg_rom, _chr_rom) = rom_bytes.split_at(usize::from(prg_rom_chunks) * 0x4000);
+    let chr_rom_chunks = header_bytes[5];
+    let (prg_rom, chr_rom) = rom_bytes.split_at(usize::from(prg_rom_chunks) * 0x4000);
+    let chr_rom = &chr_rom[..usize::from(chr_rom_chunks) * 0x2000];
 
     let is_mirroring_horizontal = (header_bytes[6] & (1 << 0)) != 0;
-
-    AnyCartridge::Nrom(Nrom::new(prg_rom, is_mirroring_horizontal))
+    let has_battery = (header_bytes[6] & (1 << 1)) != 0;
+
+    AnyCartridge::Nrom(Nrom::new(
+        prg_rom,
+        chr_rom,
+        is_mirroring_horizontal,
+        has_battery.then_some(battery_ram),
+    ))
 }
 
-pub trait Cartridge {
+/// A cartridge, whose mutable state (RAM, mapper registers) is included in
+/// save states through its [`SaveState`] implementation.
+pub trait Cartridge: SaveState {
     fn read_is_mirroring_horizontal<Visitor: super::Visitor>(
         &self,
         visitor: &mut Visitor,
@@ -39,6 +60,19 @@ pub trait Cartridge {
         value: Visitor::U8,
     );
 
+    fn read_chr<Visitor: super::Visitor>(
+        &self,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+    ) -> Visitor::U8;
+
+    fn write_chr<Visitor: super::Visitor>(
+        &mut self,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+        value: Visitor::U8,
+    );
+
     // TODO: obsolete all functions below with an interpreting Visitor
 
     fn reset_vector(&self) -> u16;
@@ -46,8 +80,17 @@ pub trait Cartridge {
     fn peek_prg_rom(&self, address: u16) -> u8;
 
     fn peek_prg_ram(&self, address: u16) -> u8;
+
+    fn peek_chr(&self, address: u16) -> u8;
+
+    fn peek_is_mirroring_horizontal(&self) -> bool;
+
+    /// The contents of the battery-backed RAM, if any, which the host should
+    /// persist to keep saved progress.
+    fn battery_ram(&self) -> Option<&[u8]>;
 }
 
+#[derive(Clone)]
 pub enum AnyCartridge {
     Nrom(Nrom),
 }
@@ -93,6 +136,27 @@ impl Cartridge for AnyCartridge {
         }
     }
 
+    fn read_chr<Visitor: super::Visitor>(
+        &self,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+    ) -> Visitor::U8 {
+        match self {
+            AnyCartridge::Nrom(cartridge) => cartridge.read_chr(visitor, address),
+        }
+    }
+
+    fn write_chr<Visitor: super::Visitor>(
+        &mut self,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+        value: Visitor::U8,
+    ) {
+        match self {
+            AnyCartridge::Nrom(cartridge) => cartridge.write_chr(visitor, address, value),
+        }
+    }
+
     fn reset_vector(&self) -> u16 {
         match self {
             AnyCartridge::Nrom(cartridge) => cartridge.reset_vector(),
@@ -110,4 +174,79 @@ impl Cartridge for AnyCartridge {
             AnyCartridge::Nrom(cartridge) => cartridge.peek_prg_ram(address),
         }
     }
+
+    fn peek_chr(&self, address: u16) -> u8 {
+        match self {
+            AnyCartridge::Nrom(cartridge) => cartridge.peek_chr(address),
+        }
+    }
+
+    fn peek_is_mirroring_horizontal(&self) -> bool {
+        match self {
+            AnyCartridge::Nrom(cartridge) => cartridge.peek_is_mirroring_horizontal(),
+        }
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        match self {
+            AnyCartridge::Nrom(cartridge) => cartridge.battery_ram(),
+        }
+    }
+}
+
+impl SaveState for AnyCartridge {
+    fn save_state(&self, writer: &mut StateWriter) {
+        match self {
+            AnyCartridge::Nrom(cartridge) => cartridge.save_state(writer),
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        match self {
+            AnyCartridge::Nrom(cartridge) => cartridge.load_state(reader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Nopt, test_utils};
+
+    // SEI; LDA #$42; STA $6001; loop: JMP loop
+    const PROGRAM: [u8; 9] = [0x78, 0xa9, 0x42, 0x8d, 0x01, 0x60, 0x4c, 0x06, 0x80];
+
+    fn rom(has_battery: bool) -> Vec<u8> {
+        let header = [b'N', b'E', b'S', 0x1a, 1, 1, u8::from(has_battery) << 1];
+        let mut rom = header.to_vec();
+        rom.resize(0x10, 0);
+        rom.extend(test_utils::prg_rom(&PROGRAM, &[0x40], &[0x40]));
+        rom.resize(0x10 + 0x4000 + 0x2000, 0);
+        rom
+    }
+
+    #[test]
+    fn battery_ram_is_restored() {
+        let cartridge = from_bytes_with_header_and_battery_ram(&rom(true), &[1, 2, 3]);
+        let battery_ram = cartridge.battery_ram().unwrap();
+        assert_eq!(battery_ram.len(), 0x2000);
+        assert_eq!(battery_ram[..4], [1, 2, 3, 0]);
+        assert_eq!(cartridge.peek_prg_ram(0x0002), 3);
+    }
+
+    #[test]
+    fn battery_ram_is_only_present_if_declared() {
+        let cartridge = from_bytes_with_header_and_battery_ram(&rom(false), &[1, 2, 3]);
+        assert!(cartridge.battery_ram().is_none());
+        assert_eq!(cartridge.peek_prg_ram(0x0002), 0);
+    }
+
+    #[test]
+    fn battery_ram_holds_writes() {
+        let mut nopt = Nopt::new(from_bytes_with_header(&rom(true)));
+        for _ in 0..3 {
+            nopt.run();
+        }
+        assert_eq!(nopt.nes().cartridge.battery_ram().unwrap()[..2], [0, 0x42]);
+    }
 }
@@ -1,22 +1,50 @@
-use crate::cartridge::Cartridge;
+use crate::{
+    cartridge::Cartridge,
+    compiler::frontend::nes::save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
 
+#[derive(Clone)]
 pub struct Nrom {
     is_mirroring_horizontal: bool,
     prg_ram: [u8; 0x2000],
     prg_rom: [u8; 0x8000],
+    chr: [u8; 0x2000],
+    has_chr_ram: bool,
+    has_battery: bool,
 }
 
 impl Nrom {
+    /// Creates a cartridge with battery-backed PRG RAM if `battery_ram` holds
+    /// its saved contents, which may be shorter than the RAM.
     #[must_use]
-    pub fn new(prg_rom: &[u8], is_mirroring_horizontal: bool) -> Self {
+    pub fn new(
+        prg_rom: &[u8],
+        chr_rom: &[u8],
+        is_mirroring_horizontal: bool,
+        battery_ram: Option<&[u8]>,
+    ) -> Self {
+        let mut prg_ram = [0; 0x2000];
+        if let Some(battery_ram) = battery_ram {
+            let length = battery_ram.len().min(prg_ram.len());
+            prg_ram[..length].copy_from_slice(&battery_ram[..length]);
+        }
+
         Self {
             is_mirroring_horizontal,
-            prg_ram: [0; 0x2000],
+            prg_ram,
             prg_rom: match prg_rom.len() {
                 0x4000 => [prg_rom, prg_rom].concat().try_into().unwrap(),
                 0x8000 => prg_rom.try_into().unwrap(),
                 _ => unimplemented!("NROM cartridge with PRG ROM size 0x{:x}", prg_rom.len()),
             },
+            chr: match chr_rom.len() {
+                0 => [0; 0x2000],
+                0x2000 => chr_rom.try_into().unwrap(),
+                _ => unimplemented!("NROM cartridge with CHR ROM size 0x{:x}", chr_rom.len()),
+            },
+            // no CHR ROM means the cartridge provides CHR RAM instead
+            has_chr_ram: chr_rom.is_empty(),
+            has_battery: battery_ram.is_some(),
         }
     }
 }
@@ -56,6 +84,28 @@ impl Cartridge for Nrom {
         visitor.set_memory_with_offset_u8(self.prg_ram.as_mut_ptr(), address, value);
     }
 
+    fn read_chr<Visitor: crate::compiler::frontend::Visitor>(
+        &self,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+    ) -> Visitor::U8 {
+        visitor.memory_with_offset_u8(self.chr.as_ptr(), address)
+    }
+
+    fn write_chr<Visitor: crate::compiler::frontend::Visitor>(
+        &mut self,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+        value: Visitor::U8,
+    ) {
+        let chr = self.chr.as_mut_ptr();
+        let has_chr_ram = visitor.memory_bool(&raw const self.has_chr_ram);
+        visitor.r#if(has_chr_ram, |mut visitor| {
+            visitor.set_memory_with_offset_u8(chr, address, value);
+            visitor.terminate(None);
+        });
+    }
+
     fn reset_vector(&self) -> u16 {
         u16::from_le_bytes(
             self.prg_rom[self.prg_rom.len() - 0x4..][..size_of::<u16>()]
@@ -71,4 +121,33 @@ impl Cartridge for Nrom {
     fn peek_prg_ram(&self, address: u16) -> u8 {
         self.prg_ram[usize::from(address)]
     }
+
+    fn peek_chr(&self, address: u16) -> u8 {
+        self.chr[usize::from(address)]
+    }
+
+    fn peek_is_mirroring_horizontal(&self) -> bool {
+        self.is_mirroring_horizontal
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.has_battery.then_some(&self.prg_ram[..])
+    }
+}
+
+impl SaveState for Nrom {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.prg_ram.save_state(writer);
+        if self.has_chr_ram {
+            self.chr.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.prg_ram.load_state(reader)?;
+        if self.has_chr_ram {
+            self.chr.load_state(reader)?;
+        }
+        Ok(())
+    }
 }
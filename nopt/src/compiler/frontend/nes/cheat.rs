@@ -0,0 +1,292 @@
+use std::{fmt, str::FromStr};
+
+/// A patch applied to reads from PRG ROM, as done by the Game Genie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    /// The CPU address, between 0x8000 and 0xffff, whose reads are patched.
+    pub address: u16,
+    pub value: u8,
+    /// The value which must be in the ROM for the patch to apply, if any.
+    pub compare: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseCheatError {
+    /// A Game Genie code is neither 6 nor 8 letters long.
+    InvalidLength,
+    /// A Game Genie code contains a letter not used by the Game Genie.
+    InvalidLetter(char),
+    /// A raw cheat is not of the form `address=value[?compare]` in
+    /// hexadecimal.
+    InvalidFormat,
+    /// The address of a raw cheat is outside of PRG ROM.
+    AddressOutOfRange(u16),
+}
+
+impl fmt::Display for ParseCheatError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength => write!(formatter, "Game Genie code must be 6 or 8 letters"),
+            Self::InvalidLetter(letter) => {
+                write!(formatter, "invalid Game Genie letter {letter:?}")
+            }
+            Self::InvalidFormat => write!(formatter, "cheat must be of the form address=value"),
+            Self::AddressOutOfRange(address) => {
+                write!(formatter, "cheat address 0x{address:04x} is not in PRG ROM")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseCheatError {}
+
+/// The letters of Game Genie codes, in the order of the values they encode.
+const GAME_GENIE_LETTERS: [char; 16] = [
+    'A', 'P', 'Z', 'L', 'G', 'I', 'T', 'Y', 'E', 'O', 'X', 'U', 'K', 'S', 'V', 'N',
+];
+
+impl Cheat {
+    /// Decodes a 6 or 8-letter Game Genie code, ignoring case.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the code is not a valid Game Genie code.
+    pub fn from_game_genie(code: &str) -> Result<Self, ParseCheatError> {
+        let n = code
+            .chars()
+            .map(|letter| {
+                GAME_GENIE_LETTERS
+                    .iter()
+                    .position(|game_genie_letter| *game_genie_letter == letter.to_ascii_uppercase())
+                    .map(|value| u8::try_from(value).unwrap())
+                    .ok_or(ParseCheatError::InvalidLetter(letter))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if n.len() != 6 && n.len() != 8 {
+            return Err(ParseCheatError::InvalidLength);
+        }
+
+        // the bits of the address and values are scrambled across the letters
+        let address = 0x8000
+            | (u16::from(n[3] & 0b0111) << 12)
+            | (u16::from(n[5] & 0b0111) << 8)
+            | (u16::from(n[4] & 0b1000) << 8)
+            | (u16::from(n[2] & 0b0111) << 4)
+            | (u16::from(n[1] & 0b1000) << 4)
+            | u16::from(n[4] & 0b0111)
+            | u16::from(n[3] & 0b1000);
+        let value_without_bit_3 = ((n[1] & 0b0111) << 4) | ((n[0] & 0b1000) << 4) | (n[0] & 0b0111);
+        if n.len() == 6 {
+            Ok(Self {
+                address,
+                value: value_without_bit_3 | (n[5] & 0b1000),
+                compare: None,
+            })
+        } else {
+            Ok(Self {
+                address,
+                value: value_without_bit_3 | (n[7] & 0b1000),
+                compare: Some(
+                    ((n[7] & 0b0111) << 4)
+                        | ((n[6] & 0b1000) << 4)
+                        | (n[6] & 0b0111)
+                        | (n[5] & 0b1000),
+                ),
+            })
+        }
+    }
+
+    /// Applies the last matching cheat to a value read from the given address.
+    pub(super) fn patch(cheats: &[Self], address: u16, value: u8) -> u8 {
+        cheats
+            .iter()
+            .rev()
+            .find(|cheat| {
+                cheat.address == address && cheat.compare.is_none_or(|compare| compare == value)
+            })
+            .map_or(value, |cheat| cheat.value)
+    }
+
+    /// Emits the equivalent of [`Cheat::patch`] for a value read from the
+    /// given address. Cheats which cannot apply, as the address is known at
+    /// compile time, are skipped. The cheats are baked into the emitted code,
+    /// which must be discarded once they change.
+    pub(super) fn visit_patch<Visitor: super::Visitor>(
+        cheats: &[Self],
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+        value: Visitor::U8,
+    ) -> Visitor::U8 {
+        let known_address = visitor.known_u16(address);
+        let mut patched_value = value;
+        for cheat in cheats {
+            if known_address.is_some_and(|address| address != cheat.address) {
+                continue;
+            }
+
+            let mut condition = if known_address.is_none() {
+                Some(visitor.is_in_range(address, cheat.address..=cheat.address))
+            } else {
+                None
+            };
+            if let Some(compare) = cheat.compare {
+                let compare = visitor.immediate_u8(compare);
+                let difference = visitor.xor(value, compare);
+                let is_equal = visitor.is_zero(difference);
+                condition = Some(match condition {
+                    Some(condition) => visitor.and_u1(condition, is_equal),
+                    None => is_equal,
+                });
+            }
+            let Some(condition) = condition else {
+                patched_value = visitor.immediate_u8(cheat.value);
+                continue;
+            };
+            patched_value = visitor.if_else_with_result(
+                condition,
+                |mut visitor| {
+                    let cheat_value = visitor.immediate_u8(cheat.value);
+                    visitor.terminate(Some(cheat_value));
+                },
+                |visitor| visitor.terminate(Some(patched_value)),
+            );
+        }
+        patched_value
+    }
+}
+
+/// Parses a raw cheat of the form `address=value` or `address=value?compare`,
+/// with each number in hexadecimal.
+impl FromStr for Cheat {
+    type Err = ParseCheatError;
+
+    fn from_str(cheat: &str) -> Result<Self, Self::Err> {
+        let (address, value) = cheat
+            .split_once('=')
+            .ok_or(ParseCheatError::InvalidFormat)?;
+        let (value, compare) = match value.split_once('?') {
+            Some((value, compare)) => (value, Some(compare)),
+            None => (value, None),
+        };
+
+        let address =
+            u16::from_str_radix(address, 16).map_err(|_| ParseCheatError::InvalidFormat)?;
+        if address < 0x8000 {
+            return Err(ParseCheatError::AddressOutOfRange(address));
+        }
+        let parse_u8 =
+            |value| u8::from_str_radix(value, 16).map_err(|_| ParseCheatError::InvalidFormat);
+        Ok(Self {
+            address,
+            value: parse_u8(value)?,
+            compare: compare.map(parse_u8).transpose()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    // SEI; LDA $8010; STA $00; LDX #$10; LDA $8000,X; STA $01; loop: JMP loop;
+    // followed by the value read at $8010
+    const PROGRAM: [u8; 17] = [
+        0x78, 0xad, 0x10, 0x80, 0x85, 0x00, 0xa2, 0x10, 0xbd, 0x00, 0x80, 0x85, 0x01, 0x4c, 0x0d,
+        0x80, 0x11,
+    ];
+
+    /// Reads the patched value through a known and an unknown address, with
+    /// and without compilation.
+    fn patched_values(cheats: &[&str]) -> [[u8; 2]; 2] {
+        [false, true].map(|is_jit_enabled| {
+            let mut nopt = test_utils::nopt(&PROGRAM);
+            nopt.set_jit_enabled(is_jit_enabled);
+            nopt.set_cheats(cheats.iter().map(|cheat| cheat.parse().unwrap()).collect());
+            while nopt.nes().cpu.pc != 0x800d {
+                nopt.run();
+            }
+            [nopt.nes().cpu.ram[0], nopt.nes().cpu.ram[1]]
+        })
+    }
+
+    #[test]
+    fn game_genie_codes_are_decoded() {
+        let cheat = |address, value, compare| Cheat {
+            address,
+            value,
+            compare,
+        };
+        assert_eq!(
+            Cheat::from_game_genie("SXIOPO"),
+            Ok(cheat(0x91d9, 0xad, None))
+        );
+        assert_eq!(
+            Cheat::from_game_genie("gossip"),
+            Ok(cheat(0xd1dd, 0x14, None))
+        );
+        assert_eq!(
+            Cheat::from_game_genie("SLXPLOVS"),
+            Ok(cheat(0x9123, 0xbd, Some(0xde)))
+        );
+    }
+
+    #[test]
+    fn invalid_game_genie_codes_are_rejected() {
+        assert_eq!(
+            Cheat::from_game_genie("SXIOP"),
+            Err(ParseCheatError::InvalidLength)
+        );
+        assert_eq!(
+            Cheat::from_game_genie("SXIOPOP"),
+            Err(ParseCheatError::InvalidLength)
+        );
+        assert_eq!(
+            Cheat::from_game_genie("SXIOPB"),
+            Err(ParseCheatError::InvalidLetter('B'))
+        );
+    }
+
+    #[test]
+    fn raw_cheats_are_parsed() {
+        assert_eq!(
+            "91d9=ad".parse(),
+            Ok(Cheat {
+                address: 0x91d9,
+                value: 0xad,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            "9123=BD?DE".parse(),
+            Ok(Cheat {
+                address: 0x9123,
+                value: 0xbd,
+                compare: Some(0xde),
+            })
+        );
+        assert_eq!(
+            "7fff=00".parse::<Cheat>(),
+            Err(ParseCheatError::AddressOutOfRange(0x7fff))
+        );
+        for cheat in ["8000", "8000=", "8000=100", "8000=00?", "x=00"] {
+            assert_eq!(cheat.parse::<Cheat>(), Err(ParseCheatError::InvalidFormat));
+        }
+    }
+
+    #[test]
+    fn reads_are_patched() {
+        assert_eq!(patched_values(&[]), [[0x11; 2]; 2]);
+        assert_eq!(patched_values(&["8011=22"]), [[0x11; 2]; 2]);
+        assert_eq!(patched_values(&["8010=22"]), [[0x22; 2]; 2]);
+        assert_eq!(patched_values(&["8010=22?11"]), [[0x22; 2]; 2]);
+        assert_eq!(patched_values(&["8010=22?33"]), [[0x11; 2]; 2]);
+    }
+
+    #[test]
+    fn last_matching_cheat_applies() {
+        assert_eq!(patched_values(&["8010=22", "8010=33?11"]), [[0x33; 2]; 2]);
+        assert_eq!(patched_values(&["8010=33?11", "8010=22"]), [[0x22; 2]; 2]);
+        assert_eq!(patched_values(&["8010=22", "8010=33?44"]), [[0x22; 2]; 2]);
+    }
+}
@@ -1,10 +1,16 @@
 use crate::{
-    compiler::frontend::nes::{Nes, Ppu},
+    compiler::frontend::nes::{
+        Apu, Nes, Ppu,
+        cheat::Cheat,
+        save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+        watchpoint::{Access, Watchpoint},
+    },
     nes_assembly,
 };
 use std::ops::RangeInclusive;
 use tracing::warn;
 
+#[derive(Clone)]
 pub struct Cpu {
     pub ram: [u8; 0x800],
     pub a: u8,
@@ -13,6 +19,10 @@ pub struct Cpu {
     pub p: u8,
     pub s: u8,
     pub pc: u16,
+    pub oam_dma_page: u8,
+    pub is_oam_dma_pending: bool,
+    pub cycle_count: u64,
+    pub instruction_cycles: u8,
 }
 
 impl Cpu {
@@ -25,6 +35,10 @@ impl Cpu {
             p: 0,
             s: 0,
             pc,
+            oam_dma_page: 0,
+            is_oam_dma_pending: false,
+            cycle_count: 0,
+            instruction_cycles: 0,
         }
     }
 
@@ -39,6 +53,9 @@ impl Cpu {
 
         let mut jump_target = None;
 
+        let cycles = visitor.immediate_u8(cpu_instruction.operation().cycles());
+        visitor.set_memory_u8(&raw mut nes.cpu.instruction_cycles, cycles);
+
         match cpu_instruction.operation().mnemonic() {
             nes_assembly::Mnemonic::Adc => {
                 let operand_0 = visitor.memory_u8(&raw const nes.cpu.a);
@@ -79,21 +96,15 @@ impl Cpu {
             nes_assembly::Mnemonic::Bcc => {
                 let c = Self::cpu_c(nes, visitor);
                 let not_c = visitor.not(c);
-                let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
-                let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
-                jump_target = Some(visitor.select(not_c, address_if_true, address_if_false));
+                jump_target = Some(Self::branch(nes, visitor, cpu_instruction, not_c));
             }
             nes_assembly::Mnemonic::Bcs => {
                 let c = Self::cpu_c(nes, visitor);
-                let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
-                let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
-                jump_target = Some(visitor.select(c, address_if_true, address_if_false));
+                jump_target = Some(Self::branch(nes, visitor, cpu_instruction, c));
             }
             nes_assembly::Mnemonic::Beq => {
                 let z = Self::cpu_z(nes, visitor);
-                let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
-                let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
-                jump_target = Some(visitor.select(z, address_if_true, address_if_false));
+                jump_target = Some(Self::branch(nes, visitor, cpu_instruction, z));
             }
             nes_assembly::Mnemonic::Bit => {
                 let operand = Self::read_operand_u8(nes, visitor, cpu_instruction);
@@ -109,23 +120,17 @@ impl Cpu {
             }
             nes_assembly::Mnemonic::Bmi => {
                 let n = Self::cpu_n(nes, visitor);
-                let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
-                let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
-                jump_target = Some(visitor.select(n, address_if_true, address_if_false));
+                jump_target = Some(Self::branch(nes, visitor, cpu_instruction, n));
             }
             nes_assembly::Mnemonic::Bne => {
                 let z = Self::cpu_z(nes, visitor);
                 let not_z = visitor.not(z);
-                let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
-                let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
-                jump_target = Some(visitor.select(not_z, address_if_true, address_if_false));
+                jump_target = Some(Self::branch(nes, visitor, cpu_instruction, not_z));
             }
             nes_assembly::Mnemonic::Bpl => {
                 let n = Self::cpu_n(nes, visitor);
                 let not_n = visitor.not(n);
-                let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
-                let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
-                jump_target = Some(visitor.select(not_n, address_if_true, address_if_false));
+                jump_target = Some(Self::branch(nes, visitor, cpu_instruction, not_n));
             }
             nes_assembly::Mnemonic::Brk => {
                 let r#true = visitor.immediate_u1(true);
@@ -149,15 +154,11 @@ impl Cpu {
             nes_assembly::Mnemonic::Bvc => {
                 let v = Self::cpu_v(nes, visitor);
                 let not_v = visitor.not(v);
-                let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
-                let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
-                jump_target = Some(visitor.select(not_v, address_if_true, address_if_false));
+                jump_target = Some(Self::branch(nes, visitor, cpu_instruction, not_v));
             }
             nes_assembly::Mnemonic::Bvs => {
                 let v = Self::cpu_v(nes, visitor);
-                let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
-                let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
-                jump_target = Some(visitor.select(v, address_if_true, address_if_false));
+                jump_target = Some(Self::branch(nes, visitor, cpu_instruction, v));
             }
             nes_assembly::Mnemonic::Clc => {
                 let r#false = visitor.immediate_u1(false);
@@ -479,6 +480,85 @@ impl Cpu {
         owned_visitor.terminate(None);
     }
 
+    pub(super) fn interrupt<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        handler_address: u16,
+    ) {
+        let r#false = visitor.immediate_u1(false);
+        let r#true = visitor.immediate_u1(true);
+
+        let pc = visitor.memory_u16(&raw const nes.cpu.pc);
+        let handler_address = visitor.immediate_u16(handler_address);
+        let handler = Self::read_u16_deref(nes, visitor, handler_address);
+
+        Self::set_cpu_unused_flag(nes, visitor, r#true);
+        Self::set_cpu_b(nes, visitor, r#false);
+
+        let p = visitor.memory_u8(&raw const nes.cpu.p);
+
+        Self::set_cpu_i(nes, visitor, r#true);
+        Self::push_u16(nes, visitor, pc);
+        Self::push_u8(nes, visitor, p);
+        visitor.set_memory_u16(&raw mut nes.cpu.pc, handler);
+
+        let cycles = visitor.immediate_u8(7);
+        visitor.set_memory_u8(&raw mut nes.cpu.instruction_cycles, cycles);
+    }
+
+    fn branch<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        cpu_instruction: &nes_assembly::Instruction,
+        condition: Visitor::U1,
+    ) -> Visitor::U16 {
+        let address_if_true = Self::read_operand_u16(nes, visitor, cpu_instruction);
+        let address_if_false = visitor.immediate_u16(cpu_instruction.address_end());
+
+        // taken branches take an extra cycle, plus another one if the target is
+        // on a different page
+        let target = cpu_instruction
+            .address_end()
+            .wrapping_add_signed(i16::from(cpu_instruction.operand_i8()));
+        let extra_cycles = if target & 0xff00 == cpu_instruction.address_end() & 0xff00 {
+            1
+        } else {
+            2
+        };
+        Self::add_cycles_if(nes, visitor, condition, extra_cycles);
+
+        visitor.select(condition, address_if_true, address_if_false)
+    }
+
+    fn add_cycles_if<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        condition: Visitor::U1,
+        cycles: u8,
+    ) {
+        let instruction_cycles = &raw mut nes.cpu.instruction_cycles;
+        visitor.r#if(condition, |mut visitor| {
+            let cycles = visitor.immediate_u8(cycles);
+            let old_instruction_cycles = visitor.memory_u8(instruction_cycles.cast_const());
+            let new_instruction_cycles = visitor.add_u8(old_instruction_cycles, cycles);
+            visitor.set_memory_u8(instruction_cycles, new_instruction_cycles);
+            visitor.terminate(None);
+        });
+    }
+
+    fn add_page_crossing_cycle<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        cpu_instruction: &nes_assembly::Instruction,
+        base_address_low: Visitor::U8,
+        index: Visitor::U8,
+    ) {
+        if cpu_instruction.operation().has_page_crossing_penalty() {
+            let is_page_crossed = visitor.add_u8_carry(base_address_low, index);
+            Self::add_cycles_if(nes, visitor, is_page_crossed, 1);
+        }
+    }
+
     fn read_u16_deref<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
         nes: &mut Nes<Cartridge>,
         visitor: &mut Visitor,
@@ -724,6 +804,10 @@ impl Cpu {
                 let x = visitor.memory_u8(&raw const nes.cpu.x);
                 let operand_0 = visitor.immediate_u16(cpu_instruction.operand_u16());
                 let operand_1 = visitor.concatenate(n0, x);
+
+                let operand_0_low = visitor.low_byte(operand_0);
+                Self::add_page_crossing_cycle(nes, visitor, cpu_instruction, operand_0_low, x);
+
                 visitor.add_u16(operand_0, operand_1)
             }
             nes_assembly::AddressingMode::AbsoluteY => {
@@ -732,6 +816,10 @@ impl Cpu {
                 let y = visitor.memory_u8(&raw const nes.cpu.y);
                 let y_u16 = visitor.concatenate(n0, y);
                 let operand = visitor.immediate_u16(cpu_instruction.operand_u16());
+
+                let operand_low = visitor.low_byte(operand);
+                Self::add_page_crossing_cycle(nes, visitor, cpu_instruction, operand_low, y);
+
                 visitor.add_u16(operand, y_u16)
             }
             nes_assembly::AddressingMode::Accumulator
@@ -746,6 +834,10 @@ impl Cpu {
                 let operand_0 = Self::read_u16_deref(nes, visitor, operand);
                 let y = visitor.memory_u8(&raw const nes.cpu.y);
                 let operand_1 = visitor.concatenate(n0, y);
+
+                let operand_0_low = visitor.low_byte(operand_0);
+                Self::add_page_crossing_cycle(nes, visitor, cpu_instruction, operand_0_low, y);
+
                 visitor.add_u16(operand_0, operand_1)
             }
             nes_assembly::AddressingMode::XIndirect => {
@@ -859,6 +951,22 @@ impl Cpu {
         nes: &mut Nes<Cartridge>,
         visitor: &mut Visitor,
         address: Visitor::U16,
+    ) -> Visitor::U8 {
+        let value = Self::read_without_watchpoints(nes, visitor, address);
+        Watchpoint::visit_access(nes, visitor, Access::Read, address, value);
+        value
+    }
+
+    /// Reads like [`Cpu::read`], but for DMA transfers, which are not
+    /// accesses made by the instruction being run and so do not trigger
+    /// watchpoints.
+    pub(super) fn read_without_watchpoints<
+        Cartridge: crate::cartridge::Cartridge,
+        Visitor: super::Visitor,
+    >(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
     ) -> Visitor::U8 {
         let mut if_address_in_range =
             |visitor: &mut Visitor,
@@ -899,9 +1007,39 @@ impl Cpu {
         );
         let value = if_address_in_range(
             visitor,
-            0x2007..=0x2007,
-            |nes, mut visitor, _| {
-                let value = Ppu::read_ppudata(nes, &mut visitor);
+            0x2000..=0x3fff,
+            |nes, mut visitor, address| {
+                let value = Ppu::read_register(nes, &mut visitor, address);
+                visitor.terminate(Some(value));
+            },
+            value,
+        );
+        let value = if_address_in_range(
+            visitor,
+            0x4015..=0x4015,
+            |nes, mut visitor, address| {
+                let value = Apu::read_status(nes, &mut visitor);
+                let value = Self::with_open_bus_bits(&mut visitor, address, 0b0010_0000, value);
+                visitor.terminate(Some(value));
+            },
+            value,
+        );
+        let value = if_address_in_range(
+            visitor,
+            0x4016..=0x4016,
+            |nes, mut visitor, address| {
+                let value = Nes::read_input_device::<_, 0>(nes, &mut visitor);
+                let value = Self::with_open_bus_bits(&mut visitor, address, 0b1110_0000, value);
+                visitor.terminate(Some(value));
+            },
+            value,
+        );
+        let value = if_address_in_range(
+            visitor,
+            0x4017..=0x4017,
+            |nes, mut visitor, address| {
+                let value = Nes::read_input_device::<_, 1>(nes, &mut visitor);
+                let value = Self::with_open_bus_bits(&mut visitor, address, 0b1110_0000, value);
                 visitor.terminate(Some(value));
             },
             value,
@@ -922,8 +1060,9 @@ impl Cpu {
             0x8000..=0xffff,
             |nes, mut visitor, address| {
                 let address_mask = visitor.immediate_u16(0x7fff);
-                let address = visitor.and_u16(address, address_mask);
-                let value = nes.cartridge.read_prg_rom(&mut visitor, address);
+                let rom_address = visitor.and_u16(address, address_mask);
+                let value = nes.cartridge.read_prg_rom(&mut visitor, rom_address);
+                let value = Cheat::visit_patch(&nes.cheats, &mut visitor, address, value);
                 visitor.terminate(Some(value));
             },
             value,
@@ -964,16 +1103,32 @@ impl Cpu {
             visitor.set_memory_with_offset_u8(nes.cpu.ram.as_mut_ptr(), address, value);
             visitor.terminate(None);
         });
-        if_address_in_range(0x2000..=0x2000, |nes, mut visitor, _, value| {
-            visitor.set_memory_u8(&raw mut nes.ppu.control_register, value);
+        if_address_in_range(0x2000..=0x3fff, |nes, mut visitor, address, value| {
+            Ppu::write_register(nes, &mut visitor, address, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x4000..=0x4013, |nes, mut visitor, address, value| {
+            Apu::write_register(nes, &mut visitor, address, value);
             visitor.terminate(None);
         });
-        if_address_in_range(0x2006..=0x2006, |nes, mut visitor, _, value| {
-            nes.ppu.write_ppuaddr(&mut visitor, value);
+        if_address_in_range(0x4014..=0x4014, |nes, mut visitor, _, value| {
+            // the transfer itself is performed by `run_oam_dma` once the
+            // current instruction has finished
+            let r#true = visitor.immediate_u1(true);
+            visitor.set_memory_u8(&raw mut nes.cpu.oam_dma_page, value);
+            visitor.set_memory_bool(&raw mut nes.cpu.is_oam_dma_pending, r#true);
             visitor.terminate(None);
         });
-        if_address_in_range(0x2007..=0x2007, |nes, mut visitor, _, value| {
-            Ppu::write_ppudata(nes, &mut visitor, value);
+        if_address_in_range(0x4015..=0x4015, |nes, mut visitor, address, value| {
+            Apu::write_register(nes, &mut visitor, address, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x4016..=0x4016, |nes, mut visitor, _, value| {
+            Nes::write_input_devices(nes, &mut visitor, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x4017..=0x4017, |nes, mut visitor, address, value| {
+            Apu::write_register(nes, &mut visitor, address, value);
             visitor.terminate(None);
         });
         if_address_in_range(0x6000..=0x7fff, |nes, mut visitor, address, value| {
@@ -982,5 +1137,134 @@ impl Cpu {
             nes.cartridge.write_prg_ram(&mut visitor, address, value);
             visitor.terminate(None);
         });
+
+        Watchpoint::visit_access(nes, visitor, Access::Write, address, value);
+    }
+
+    /// Fills in the bits of a value read from an I/O port which does not
+    /// drive them. They retain the last value on the data bus, which is
+    /// normally the high byte of the address.
+    fn with_open_bus_bits<Visitor: super::Visitor>(
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+        open_bus_mask: u8,
+        value: Visitor::U8,
+    ) -> Visitor::U8 {
+        let open_bus_mask = visitor.immediate_u8(open_bus_mask);
+        let address_high = visitor.high_byte(address);
+        let open_bus = visitor.and_u8(address_high, open_bus_mask);
+        visitor.or(value, open_bus)
+    }
+
+    pub(super) fn run_oam_dma<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+    ) {
+        let r#false = visitor.immediate_u1(false);
+
+        let page = visitor.memory_u8(&raw const nes.cpu.oam_dma_page);
+        for offset in 0..=u8::MAX {
+            let offset = visitor.immediate_u8(offset);
+            let address = visitor.concatenate(page, offset);
+            let value = Self::read_without_watchpoints(nes, visitor, address);
+            nes.ppu.write_oamdata(visitor, value);
+        }
+        visitor.set_memory_bool(&raw mut nes.cpu.is_oam_dma_pending, r#false);
+    }
+}
+
+impl SaveState for Cpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.ram.save_state(writer);
+        self.a.save_state(writer);
+        self.x.save_state(writer);
+        self.y.save_state(writer);
+        self.p.save_state(writer);
+        self.s.save_state(writer);
+        self.pc.save_state(writer);
+        self.oam_dma_page.save_state(writer);
+        self.is_oam_dma_pending.save_state(writer);
+        self.cycle_count.save_state(writer);
+        self.instruction_cycles.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.ram.load_state(reader)?;
+        self.a.load_state(reader)?;
+        self.x.load_state(reader)?;
+        self.y.load_state(reader)?;
+        self.p.load_state(reader)?;
+        self.s.load_state(reader)?;
+        self.pc.load_state(reader)?;
+        self.oam_dma_page.load_state(reader)?;
+        self.is_oam_dma_pending.load_state(reader)?;
+        self.cycle_count.load_state(reader)?;
+        self.instruction_cycles.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils;
+
+    fn instruction_cycles(program: &[u8], instruction_count: usize) -> Vec<u64> {
+        let mut nopt = test_utils::nopt(program);
+        (0..instruction_count)
+            .map(|_| {
+                let cycle_count = nopt.nes().cpu.cycle_count;
+                nopt.step_instruction();
+                nopt.nes().cpu.cycle_count - cycle_count
+            })
+            .collect()
+    }
+
+    #[test]
+    fn page_crossing_takes_an_extra_cycle() {
+        // SEI; LDX #$01; LDA $80fe,X; LDA $80ff,X; STA $02fe,X; STA $02ff,X
+        let program = [
+            0x78, 0xa2, 0x01, 0xbd, 0xfe, 0x80, 0xbd, 0xff, 0x80, 0x9d, 0xfe, 0x02, 0x9d, 0xff,
+            0x02,
+        ];
+        assert_eq!(instruction_cycles(&program, 6), [2, 2, 4, 5, 5, 5]);
+    }
+
+    #[test]
+    fn taken_branch_takes_extra_cycles() {
+        // SEI; BNE +$00; BEQ +$00; JMP $80fd; ...; BNE +$10, crossing a page
+        let mut program = vec![0x78, 0xd0, 0x00, 0xf0, 0x00, 0x4c, 0xfd, 0x80];
+        program.resize(0xfd, 0xea);
+        program.extend([0xd0, 0x10]);
+        assert_eq!(instruction_cycles(&program, 5), [2, 3, 2, 3, 4]);
+    }
+
+    #[test]
+    fn oam_dma_copies_page_to_oam() {
+        // SEI; LDA #$08; STA $2003; LDA #$02; STA $4014; loop: JMP loop
+        let program = [
+            0x78, 0xa9, 0x08, 0x8d, 0x03, 0x20, 0xa9, 0x02, 0x8d, 0x14, 0x40, 0x4c, 0x0b, 0x80,
+        ];
+        for is_jit_enabled in [false, true] {
+            let mut nopt = test_utils::nopt(&program);
+            nopt.set_jit_enabled(is_jit_enabled);
+            for (value, offset) in nopt.nes_mut().cpu.ram[0x200..0x300]
+                .iter_mut()
+                .zip(0..=u8::MAX)
+            {
+                *value = offset ^ 0x5a;
+            }
+            while nopt.nes().cpu.pc != 0x800b {
+                nopt.run();
+            }
+
+            let nes = nopt.nes();
+            for offset in 0..0x100 {
+                assert_eq!(
+                    nes.ppu.oam[(offset + 8) % 0x100],
+                    nes.cpu.ram[0x200 + offset]
+                );
+            }
+            assert_eq!(nes.ppu.oam_address, 0x08);
+        }
     }
 }
@@ -0,0 +1,149 @@
+use crate::compiler::frontend::nes::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter,
+};
+
+/// A picture output by the PPU.
+///
+/// Each pixel holds a 6-bit palette index in its low bits, followed by the 3
+/// color emphasis bits of PPUMASK that were active when it was output.
+#[derive(Clone)]
+pub struct Frame {
+    pixels: Box<[u16]>,
+}
+
+impl Frame {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 240;
+
+    pub(super) fn new() -> Self {
+        Self {
+            pixels: vec![0; Frame::WIDTH * Frame::HEIGHT].into_boxed_slice(),
+        }
+    }
+
+    #[must_use]
+    pub fn pixels(&self) -> &[u16] {
+        &self.pixels
+    }
+
+    #[must_use]
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * Self::WIDTH + x]
+    }
+
+    pub(super) fn set_pixel(&mut self, x: usize, y: usize, pixel: u16) {
+        self.pixels[y * Self::WIDTH + x] = pixel;
+    }
+
+    /// Converts the frame to 8-bit RGB triplets, row by row.
+    #[must_use]
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| Self::pixel_to_rgb(*pixel))
+            .collect()
+    }
+
+    #[must_use]
+    pub fn pixel_to_rgb(pixel: u16) -> [u8; 3] {
+        let palette_index = usize::from(pixel & 0x3f);
+        let emphasis = pixel >> 6;
+        let mut rgb = PALETTE[palette_index];
+
+        // the column of blacks is unaffected by emphasis
+        if palette_index & 0xf >= 0xe {
+            return rgb;
+        }
+
+        // emphasizing a color (red, green, blue) darkens the other two
+        for (channel_index, channel) in rgb.iter_mut().enumerate() {
+            let attenuating_emphasis = emphasis & !(1 << channel_index) & 0b111;
+            if attenuating_emphasis != 0 {
+                *channel = u8::try_from(u16::from(*channel) * 3 / 4).unwrap();
+            }
+        }
+        rgb
+    }
+}
+
+impl SaveState for Frame {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for pixel in &self.pixels {
+            pixel.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        for pixel in &mut self.pixels {
+            pixel.load_state(reader)?;
+        }
+        Ok(())
+    }
+}
+
+const PALETTE: [[u8; 3]; 0x40] = [
+    [0x54, 0x54, 0x54],
+    [0x00, 0x1e, 0x74],
+    [0x08, 0x10, 0x90],
+    [0x30, 0x00, 0x88],
+    [0x44, 0x00, 0x64],
+    [0x5c, 0x00, 0x30],
+    [0x54, 0x04, 0x00],
+    [0x3c, 0x18, 0x00],
+    [0x20, 0x2a, 0x00],
+    [0x08, 0x3a, 0x00],
+    [0x00, 0x40, 0x00],
+    [0x00, 0x3c, 0x00],
+    [0x00, 0x32, 0x3c],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0x98, 0x96, 0x98],
+    [0x08, 0x4c, 0xc4],
+    [0x30, 0x32, 0xec],
+    [0x5c, 0x1e, 0xe4],
+    [0x88, 0x14, 0xb0],
+    [0xa0, 0x14, 0x64],
+    [0x98, 0x22, 0x20],
+    [0x78, 0x3c, 0x00],
+    [0x54, 0x5a, 0x00],
+    [0x28, 0x72, 0x00],
+    [0x08, 0x7c, 0x00],
+    [0x00, 0x76, 0x28],
+    [0x00, 0x66, 0x78],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0xec, 0xee, 0xec],
+    [0x4c, 0x9a, 0xec],
+    [0x78, 0x7c, 0xec],
+    [0xb0, 0x62, 0xec],
+    [0xe4, 0x54, 0xec],
+    [0xec, 0x58, 0xb4],
+    [0xec, 0x6a, 0x64],
+    [0xd4, 0x88, 0x20],
+    [0xa0, 0xaa, 0x00],
+    [0x74, 0xc4, 0x00],
+    [0x4c, 0xd0, 0x20],
+    [0x38, 0xcc, 0x6c],
+    [0x38, 0xb4, 0xcc],
+    [0x3c, 0x3c, 0x3c],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+    [0xec, 0xee, 0xec],
+    [0xa8, 0xcc, 0xec],
+    [0xbc, 0xbc, 0xec],
+    [0xd4, 0xb2, 0xec],
+    [0xec, 0xae, 0xec],
+    [0xec, 0xae, 0xd4],
+    [0xec, 0xb4, 0xb0],
+    [0xe4, 0xc4, 0x90],
+    [0xcc, 0xd2, 0x78],
+    [0xb4, 0xde, 0x78],
+    [0xa8, 0xe2, 0x90],
+    [0x98, 0xe2, 0xb4],
+    [0xa0, 0xd6, 0xe4],
+    [0xa0, 0xa2, 0xa0],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00],
+];
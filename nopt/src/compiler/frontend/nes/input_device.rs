@@ -0,0 +1,88 @@
+mod arkanoid_paddle;
+mod four_score;
+mod power_pad;
+mod standard_controller;
+mod zapper;
+
+pub use arkanoid_paddle::ArkanoidPaddle;
+pub use four_score::FourScore;
+pub use power_pad::PowerPad;
+pub use standard_controller::{Buttons, StandardController};
+pub use zapper::Zapper;
+
+use crate::compiler::frontend::nes::{
+    Frame,
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+use std::any::Any;
+
+/// The state of the machine which a device may observe when read.
+pub struct InputContext<'a> {
+    pub frame: &'a Frame,
+    pub scanline: u16,
+    pub dot: u16,
+}
+
+/// A device plugged into one of the two controller ports. Its internal state
+/// is included in save states, which are only meant to be loaded with the same
+/// devices plugged in.
+pub trait InputDevice: Any + SaveState + CloneInputDevice {
+    /// Handles a write to $4016, whose bit 0 is the strobe line shared by
+    /// both ports.
+    fn write(&mut self, value: u8);
+
+    /// Handles a read of the port's register ($4016 or $4017), returning the
+    /// state of data lines D0-D4.
+    fn read(&mut self, context: &InputContext<'_>) -> u8;
+}
+
+/// Allows boxed devices to be cloned along with the rest of the machine.
+/// Implemented for every device which is [`Clone`].
+pub trait CloneInputDevice {
+    fn clone_box(&self) -> Box<dyn InputDevice>;
+}
+
+impl<Device: InputDevice + Clone> CloneInputDevice for Device {
+    fn clone_box(&self) -> Box<dyn InputDevice> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn InputDevice> {
+    fn clone(&self) -> Self {
+        self.as_ref().clone_box()
+    }
+}
+
+/// An empty port.
+#[derive(Clone)]
+pub struct Unplugged;
+
+impl InputDevice for Unplugged {
+    fn write(&mut self, _value: u8) {}
+
+    fn read(&mut self, _context: &InputContext<'_>) -> u8 {
+        0
+    }
+}
+
+impl SaveState for Unplugged {
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        Ok(())
+    }
+}
+
+/// Reads from the device the given number of times, as though outside of
+/// rendering.
+#[cfg(test)]
+fn read_repeatedly(device: &mut impl InputDevice, count: usize) -> Vec<u8> {
+    let frame = Frame::new();
+    let context = InputContext {
+        frame: &frame,
+        scanline: 0,
+        dot: 0,
+    };
+    (0..count).map(|_| device.read(&context)).collect()
+}
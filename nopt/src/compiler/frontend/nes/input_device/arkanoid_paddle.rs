@@ -0,0 +1,79 @@
+use crate::compiler::frontend::nes::{
+    input_device::{InputContext, InputDevice},
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+
+/// The controller bundled with Arkanoid, which reports the position of its
+/// knob serially on D3 and its button on D4.
+#[derive(Clone, Default)]
+pub struct ArkanoidPaddle {
+    position: u8,
+    is_button_pressed: bool,
+    shift_register: u8,
+}
+
+impl ArkanoidPaddle {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_position(&mut self, position: u8) {
+        self.position = position;
+    }
+
+    pub fn set_button_pressed(&mut self, is_button_pressed: bool) {
+        self.is_button_pressed = is_button_pressed;
+    }
+}
+
+impl InputDevice for ArkanoidPaddle {
+    fn write(&mut self, value: u8) {
+        if value & 1 != 0 {
+            // the position is reported inverted, most significant bit first
+            self.shift_register = !self.position;
+        }
+    }
+
+    fn read(&mut self, _context: &InputContext<'_>) -> u8 {
+        let value = ((self.shift_register >> 7) << 3) | (u8::from(self.is_button_pressed) << 4);
+        self.shift_register <<= 1;
+        value
+    }
+}
+
+impl SaveState for ArkanoidPaddle {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.position.save_state(writer);
+        self.is_button_pressed.save_state(writer);
+        self.shift_register.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.position.load_state(reader)?;
+        self.is_button_pressed.load_state(reader)?;
+        self.shift_register.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::frontend::nes::input_device::read_repeatedly;
+
+    #[test]
+    fn reports_inverted_position_most_significant_bit_first() {
+        let mut arkanoid_paddle = ArkanoidPaddle::new();
+        arkanoid_paddle.set_position(0b1010_0101);
+        arkanoid_paddle.set_button_pressed(true);
+        arkanoid_paddle.write(1);
+        arkanoid_paddle.write(0);
+        assert_eq!(
+            read_repeatedly(&mut arkanoid_paddle, 8),
+            [
+                0b1_0000, 0b1_1000, 0b1_0000, 0b1_1000, 0b1_1000, 0b1_0000, 0b1_1000, 0b1_0000
+            ]
+        );
+    }
+}
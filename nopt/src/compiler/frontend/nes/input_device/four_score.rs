@@ -0,0 +1,99 @@
+use crate::compiler::frontend::nes::{
+    input_device::{Buttons, InputContext, InputDevice},
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+
+/// One port of a Four Score multitap, reporting two standard controllers
+/// followed by a signature identifying the port.
+#[derive(Clone)]
+pub struct FourScore {
+    buttons: [Buttons; 2],
+    signature: u8,
+    shift_register: u32,
+    is_strobe_set: bool,
+}
+
+impl FourScore {
+    /// Creates the half of the multitap connected to the given port (0 or 1),
+    /// which hosts controllers 1 and 3, or 2 and 4 respectively.
+    #[must_use]
+    pub fn new(port: usize) -> Self {
+        Self {
+            buttons: [Buttons::empty(); 2],
+            signature: 0b0000_1000 >> port,
+            shift_register: 0,
+            is_strobe_set: false,
+        }
+    }
+
+    /// Sets the buttons held on the first (0) or second (1) controller of the
+    /// port.
+    pub fn set_buttons(&mut self, controller_index: usize, buttons: Buttons) {
+        self.buttons[controller_index] = buttons;
+    }
+
+    fn reload(&mut self) {
+        self.shift_register = u32::from(self.buttons[0].bits())
+            | (u32::from(self.buttons[1].bits()) << 8)
+            | (u32::from(self.signature) << 16);
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write(&mut self, value: u8) {
+        self.is_strobe_set = value & 1 != 0;
+        if self.is_strobe_set {
+            self.reload();
+        }
+    }
+
+    fn read(&mut self, _context: &InputContext<'_>) -> u8 {
+        if self.is_strobe_set {
+            self.reload();
+        }
+
+        let value = u8::from(self.shift_register & 1 != 0);
+        self.shift_register = (self.shift_register >> 1) | (1 << 23);
+        value
+    }
+}
+
+impl SaveState for FourScore {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.buttons.save_state(writer);
+        self.signature.save_state(writer);
+        self.shift_register.save_state(writer);
+        self.is_strobe_set.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.buttons.load_state(reader)?;
+        self.signature.load_state(reader)?;
+        self.shift_register.load_state(reader)?;
+        self.is_strobe_set.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::frontend::nes::input_device::read_repeatedly;
+
+    #[test]
+    fn reports_both_controllers_then_signature() {
+        for (port, signature) in [(0, [0, 0, 0, 1, 0, 0, 0, 0]), (1, [0, 0, 1, 0, 0, 0, 0, 0])] {
+            let mut four_score = FourScore::new(port);
+            four_score.set_buttons(0, Buttons::A | Buttons::RIGHT);
+            four_score.set_buttons(1, Buttons::B);
+            four_score.write(1);
+            four_score.write(0);
+
+            let values = read_repeatedly(&mut four_score, 25);
+            assert_eq!(values[..8], [1, 0, 0, 0, 0, 0, 0, 1], "port {port}");
+            assert_eq!(values[8..16], [0, 1, 0, 0, 0, 0, 0, 0], "port {port}");
+            assert_eq!(values[16..24], signature, "port {port}");
+            assert_eq!(values[24], 1, "port {port}");
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::compiler::frontend::nes::{
+    input_device::{InputContext, InputDevice},
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+
+/// The order in which the buttons (numbered from 1 to 12) are reported on
+/// D3 and D4.
+const D3_BUTTONS: [u8; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const D4_BUTTONS: [u8; 4] = [4, 3, 12, 8];
+
+#[derive(Clone, Default)]
+pub struct PowerPad {
+    /// The pressed buttons, with button n at bit n - 1.
+    buttons: u16,
+    d3_shift_register: u8,
+    d4_shift_register: u8,
+    is_strobe_set: bool,
+}
+
+impl PowerPad {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the pressed buttons, with button n at bit n - 1.
+    pub fn set_buttons(&mut self, buttons: u16) {
+        self.buttons = buttons;
+    }
+
+    fn reload(&mut self) {
+        let gather = |buttons: &[u8]| {
+            buttons
+                .iter()
+                .enumerate()
+                .fold(0u8, |shift_register, (bit_index, button)| {
+                    let is_pressed = self.buttons & (1 << (button - 1)) != 0;
+                    shift_register | (u8::from(is_pressed) << bit_index)
+                })
+        };
+        self.d3_shift_register = gather(&D3_BUTTONS);
+        self.d4_shift_register = gather(&D4_BUTTONS) | 0b1111_0000;
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn write(&mut self, value: u8) {
+        self.is_strobe_set = value & 1 != 0;
+        if self.is_strobe_set {
+            self.reload();
+        }
+    }
+
+    fn read(&mut self, _context: &InputContext<'_>) -> u8 {
+        if self.is_strobe_set {
+            self.reload();
+        }
+
+        let value = ((self.d3_shift_register & 1) << 3) | ((self.d4_shift_register & 1) << 4);
+        self.d3_shift_register = (self.d3_shift_register >> 1) | 0b1000_0000;
+        self.d4_shift_register = (self.d4_shift_register >> 1) | 0b1000_0000;
+        value
+    }
+}
+
+impl SaveState for PowerPad {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.buttons.save_state(writer);
+        self.d3_shift_register.save_state(writer);
+        self.d4_shift_register.save_state(writer);
+        self.is_strobe_set.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.buttons.load_state(reader)?;
+        self.d3_shift_register.load_state(reader)?;
+        self.d4_shift_register.load_state(reader)?;
+        self.is_strobe_set.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::frontend::nes::input_device::read_repeatedly;
+
+    #[test]
+    fn reports_buttons_on_d3_and_d4() {
+        let mut power_pad = PowerPad::new();
+        // buttons 1, 3 and 7
+        power_pad.set_buttons(0b0000_0100_0101);
+        power_pad.write(1);
+        power_pad.write(0);
+        assert_eq!(
+            read_repeatedly(&mut power_pad, 9),
+            [
+                0b0_0000, 0b1_1000, 0b0_0000, 0b0_0000, 0b1_0000, 0b1_0000, 0b1_0000, 0b1_1000,
+                0b1_1000
+            ]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use crate::compiler::frontend::nes::{
+    input_device::{InputContext, InputDevice},
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+use bitflags::bitflags;
+
+bitflags! {
+    /// The buttons of a standard controller, in the order they are reported
+    /// by its shift register.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Buttons: u8 {
+        const A = 1 << 0;
+        const B = 1 << 1;
+        const SELECT = 1 << 2;
+        const START = 1 << 3;
+        const UP = 1 << 4;
+        const DOWN = 1 << 5;
+        const LEFT = 1 << 6;
+        const RIGHT = 1 << 7;
+    }
+}
+
+impl SaveState for Buttons {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.bits().save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        let mut bits = 0;
+        bits.load_state(reader)?;
+        *self = Self::from_bits_retain(bits);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct StandardController {
+    buttons: Buttons,
+    shift_register: u8,
+    /// Whether the shift register is continuously reloaded with the state of
+    /// the buttons.
+    is_strobe_set: bool,
+}
+
+impl StandardController {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+    }
+}
+
+impl InputDevice for StandardController {
+    fn write(&mut self, value: u8) {
+        self.is_strobe_set = value & 1 != 0;
+        if self.is_strobe_set {
+            self.shift_register = self.buttons.bits();
+        }
+    }
+
+    fn read(&mut self, _context: &InputContext<'_>) -> u8 {
+        if self.is_strobe_set {
+            return self.buttons.bits() & 1;
+        }
+
+        // after the eight buttons, an official controller reports ones
+        let value = self.shift_register & 1;
+        self.shift_register = (self.shift_register >> 1) | 0b1000_0000;
+        value
+    }
+}
+
+impl SaveState for StandardController {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.buttons.save_state(writer);
+        self.shift_register.save_state(writer);
+        self.is_strobe_set.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.buttons.load_state(reader)?;
+        self.shift_register.load_state(reader)?;
+        self.is_strobe_set.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler::frontend::nes::input_device::read_repeatedly, test_utils};
+
+    #[test]
+    fn reports_buttons_then_ones() {
+        let mut controller = StandardController::new();
+        controller.set_buttons(Buttons::A | Buttons::START | Buttons::RIGHT);
+        controller.write(1);
+        controller.write(0);
+        assert_eq!(
+            read_repeatedly(&mut controller, 10),
+            [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn reports_a_while_strobe_is_set() {
+        let mut controller = StandardController::new();
+        controller.set_buttons(Buttons::A);
+        controller.write(1);
+        assert_eq!(read_repeatedly(&mut controller, 3), [1, 1, 1]);
+        controller.set_buttons(Buttons::B);
+        assert_eq!(read_repeatedly(&mut controller, 1), [0]);
+    }
+
+    #[test]
+    fn is_read_through_registers() {
+        let program = [
+            0x78, // SEI
+            0xa9, 0x01, 0x8d, 0x16, 0x40, // LDA #$01; STA $4016
+            0xa9, 0x00, 0x8d, 0x16, 0x40, // LDA #$00; STA $4016
+            0xa2, 0x00, // LDX #$00
+            0xad, 0x16, 0x40, // LDA $4016
+            0x95, 0x00, // STA $00,X
+            0xe8, // INX
+            0xe0, 0x09, // CPX #$09
+            0xd0, 0xf6, // BNE -$0a
+            0x4c, 0x17, 0x80, // JMP $8017
+        ];
+        for is_jit_enabled in [false, true] {
+            let mut nopt = test_utils::nopt(&program);
+            nopt.set_jit_enabled(is_jit_enabled);
+            nopt.nes_mut()
+                .set_controller_state(0, Buttons::A | Buttons::START | Buttons::RIGHT);
+            while nopt.nes().cpu.pc != 0x8017 {
+                nopt.run();
+            }
+
+            // the upper bits are those last seen on the data bus
+            assert_eq!(
+                nopt.nes().cpu.ram[..9],
+                [0x41, 0x40, 0x40, 0x41, 0x40, 0x40, 0x40, 0x41, 0x41]
+            );
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::compiler::frontend::nes::{
+    Frame,
+    input_device::{InputContext, InputDevice},
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
+
+/// The number of scanlines during which the light sensor keeps reporting a
+/// bright pixel after the beam has drawn it.
+const LIGHT_SENSE_SCANLINES: u16 = 20;
+
+#[derive(Clone, Default)]
+pub struct Zapper {
+    /// The pixel being aimed at, if the gun points at the screen.
+    aim: Option<(u16, u16)>,
+    is_trigger_pulled: bool,
+}
+
+impl Zapper {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_aim(&mut self, aim: Option<(u16, u16)>) {
+        self.aim = aim;
+    }
+
+    pub fn set_trigger_pulled(&mut self, is_trigger_pulled: bool) {
+        self.is_trigger_pulled = is_trigger_pulled;
+    }
+
+    fn senses_light(&self, context: &InputContext<'_>) -> bool {
+        let Some((x, y)) = self.aim else {
+            return false;
+        };
+        if usize::from(x) >= Frame::WIDTH || usize::from(y) >= Frame::HEIGHT {
+            return false;
+        }
+
+        // the sensor only reacts to the pixel shortly after it was drawn
+        let is_recently_drawn = match context.scanline.checked_sub(y) {
+            Some(0) => context.dot > x,
+            Some(scanlines) => scanlines < LIGHT_SENSE_SCANLINES,
+            None => false,
+        };
+        if !is_recently_drawn {
+            return false;
+        }
+
+        let pixel = context.frame.pixel(usize::from(x), usize::from(y));
+        let [red, green, blue] = Frame::pixel_to_rgb(pixel);
+        u16::from(red) + u16::from(green) + u16::from(blue) >= 0x200
+    }
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, _value: u8) {}
+
+    fn read(&mut self, context: &InputContext<'_>) -> u8 {
+        // D3 is low while light is sensed, D4 is high while the trigger is
+        // pulled
+        (u8::from(!self.senses_light(context)) << 3) | (u8::from(self.is_trigger_pulled) << 4)
+    }
+}
+
+impl SaveState for Zapper {
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn senses_recently_drawn_bright_pixels() {
+        let mut frame = Frame::new();
+        // white
+        frame.set_pixel(10, 20, 0x30);
+        let mut zapper = Zapper::new();
+        zapper.set_trigger_pulled(true);
+
+        for (aim, scanline, dot, value) in [
+            (Some((10, 20)), 20, 11, 0b1_0000),
+            (Some((10, 20)), 39, 0, 0b1_0000),
+            // not yet drawn
+            (Some((10, 20)), 20, 10, 0b1_1000),
+            // drawn too long ago
+            (Some((10, 20)), 40, 0, 0b1_1000),
+            // dark
+            (Some((11, 20)), 20, 12, 0b1_1000),
+            (None, 20, 11, 0b1_1000),
+        ] {
+            zapper.set_aim(aim);
+            let context = InputContext {
+                frame: &frame,
+                scanline,
+                dot,
+            };
+            assert_eq!(zapper.read(&context), value, "{aim:?} {scanline} {dot}");
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::{cell::Cell, rc::Rc};
+
+/// A [`super::Visitor`] which immediately performs each operation on the
+/// machine state, rather than emitting code for it.
+pub(crate) struct InterpreterVisitor {
+    result: Rc<Cell<Option<u8>>>,
+}
+
+impl InterpreterVisitor {
+    pub(crate) fn new() -> Self {
+        Self {
+            result: Rc::new(Cell::new(None)),
+        }
+    }
+}
+
+impl super::Visitor for InterpreterVisitor {
+    type U1 = bool;
+    type U8 = u8;
+    type U16 = u16;
+
+    fn immediate_u1(&mut self, value: bool) -> bool {
+        value
+    }
+
+    fn immediate_u8(&mut self, value: u8) -> u8 {
+        value
+    }
+
+    fn immediate_u16(&mut self, value: u16) -> u16 {
+        value
+    }
+
+    fn known_u16(&self, value: u16) -> Option<u16> {
+        Some(value)
+    }
+
+    fn memory_with_offset_u8(&mut self, address: *const u8, offset: u16) -> u8 {
+        unsafe { address.add(usize::from(offset)).read() }
+    }
+
+    fn set_memory_with_offset_u8(&mut self, address: *mut u8, offset: u16, value: u8) {
+        unsafe { address.add(usize::from(offset)).write(value) }
+    }
+
+    fn call_native(&mut self, function: unsafe extern "C" fn(*mut u8), argument: *mut u8) {
+        unsafe { function(argument) }
+    }
+
+    fn get_bit(&mut self, value: u8, bit_index: u8) -> bool {
+        (value >> bit_index) & 1 != 0
+    }
+
+    fn not(&mut self, operand: bool) -> bool {
+        !operand
+    }
+
+    fn is_zero(&mut self, operand: u8) -> bool {
+        operand == 0
+    }
+
+    fn rotate_left(&mut self, operand: u8, operand_carry: bool) -> u8 {
+        (operand << 1) | u8::from(operand_carry)
+    }
+
+    fn rotate_right(&mut self, operand: u8, operand_carry: bool) -> u8 {
+        (operand >> 1) | (u8::from(operand_carry) << 7)
+    }
+
+    fn low_byte(&mut self, operand: u16) -> u8 {
+        operand.to_le_bytes()[0]
+    }
+
+    fn high_byte(&mut self, operand: u16) -> u8 {
+        operand.to_le_bytes()[1]
+    }
+
+    fn less_than_or_equal(&mut self, operand_0: u16, operand_1: u16) -> bool {
+        operand_0 <= operand_1
+    }
+
+    fn select(&mut self, condition: bool, value_if_true: u16, value_if_false: u16) -> u16 {
+        if condition {
+            value_if_true
+        } else {
+            value_if_false
+        }
+    }
+
+    fn concatenate(&mut self, operand_0: u8, operand_1: u8) -> u16 {
+        u16::from_le_bytes([operand_1, operand_0])
+    }
+
+    fn or(&mut self, operand_0: u8, operand_1: u8) -> u8 {
+        operand_0 | operand_1
+    }
+
+    fn and_u1(&mut self, operand_0: bool, operand_1: bool) -> bool {
+        operand_0 && operand_1
+    }
+
+    fn and_u8(&mut self, operand_0: u8, operand_1: u8) -> u8 {
+        operand_0 & operand_1
+    }
+
+    fn xor(&mut self, operand_0: u8, operand_1: u8) -> u8 {
+        operand_0 ^ operand_1
+    }
+
+    fn add_with_carry_u8(&mut self, operand_0: u8, operand_1: u8, operand_carry: bool) -> u8 {
+        operand_0
+            .wrapping_add(operand_1)
+            .wrapping_add(u8::from(operand_carry))
+    }
+
+    fn add_with_carry_u8_carry(
+        &mut self,
+        operand_0: u8,
+        operand_1: u8,
+        operand_carry: bool,
+    ) -> bool {
+        u16::from(operand_0) + u16::from(operand_1) + u16::from(operand_carry) > 0xff
+    }
+
+    fn add_with_carry_u8_overflow(
+        &mut self,
+        operand_0: u8,
+        operand_1: u8,
+        operand_carry: bool,
+    ) -> bool {
+        let result = self.add_with_carry_u8(operand_0, operand_1, operand_carry);
+        (operand_0 ^ result) & (operand_1 ^ result) & 0x80 != 0
+    }
+
+    fn sub_with_borrow(&mut self, operand_0: u8, operand_1: u8, operand_borrow: bool) -> u8 {
+        operand_0
+            .wrapping_sub(operand_1)
+            .wrapping_sub(u8::from(operand_borrow))
+    }
+
+    fn sub_with_borrow_borrow(
+        &mut self,
+        operand_0: u8,
+        operand_1: u8,
+        operand_borrow: bool,
+    ) -> bool {
+        u16::from(operand_0) < u16::from(operand_1) + u16::from(operand_borrow)
+    }
+
+    fn sub_with_borrow_overflow(
+        &mut self,
+        operand_0: u8,
+        operand_1: u8,
+        operand_borrow: bool,
+    ) -> bool {
+        let result = self.sub_with_borrow(operand_0, operand_1, operand_borrow);
+        (operand_0 ^ result) & !(operand_1 ^ result) & 0x80 != 0
+    }
+
+    fn if_else(
+        &mut self,
+        condition: bool,
+        mut visit_true: impl FnMut(Self),
+        mut visit_false: impl FnMut(Self),
+    ) {
+        if condition {
+            visit_true(Self::new());
+        } else {
+            visit_false(Self::new());
+        }
+    }
+
+    fn if_else_with_result(
+        &mut self,
+        condition: bool,
+        mut visit_true: impl FnMut(Self),
+        mut visit_false: impl FnMut(Self),
+    ) -> u8 {
+        let result = Rc::new(Cell::new(None));
+        let visitor = Self {
+            result: Rc::clone(&result),
+        };
+        if condition {
+            visit_true(visitor);
+        } else {
+            visit_false(visitor);
+        }
+        result.get().unwrap()
+    }
+
+    fn terminate(self, argument: Option<u8>) {
+        if let Some(argument) = argument {
+            self.result.set(Some(argument));
+        }
+    }
+}
@@ -1,12 +1,58 @@
-use crate::compiler::frontend::nes::Nes;
+use crate::compiler::frontend::nes::{
+    Frame, Nes,
+    save_state::{LoadStateError, SaveState, StateReader, StateWriter},
+};
 use std::ops::RangeInclusive;
 
+#[derive(Clone, Copy, Default)]
+struct ScanlineSprite {
+    x: u8,
+    attributes: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+}
+
+#[derive(Clone)]
+#[expect(clippy::struct_excessive_bools)]
 pub struct Ppu {
     pub ram: [u8; 0x800],
     pub palette_ram: [u8; 0x20],
+    pub oam: [u8; 0x100],
     pub control_register: u8,
+    pub mask_register: u8,
+    pub status_register: u8,
+    pub oam_address: u8,
+    pub io_latch: u8,
     pub read_buffer: u8,
+    /// The VRAM address used for PPUDATA accesses and rendering ("v").
     pub current_address: u16,
+    /// The VRAM address latched by PPUCTRL, PPUSCROLL and PPUADDR writes,
+    /// holding the top-left onscreen tile ("t").
+    pub temporary_address: u16,
+    /// The horizontal scroll offset within a tile ("x").
+    pub fine_x_scroll: u8,
+    /// The first/second write toggle shared by PPUSCROLL and PPUADDR ("w").
+    pub write_toggle: bool,
+    pub scanline: u16,
+    pub dot: u16,
+    pub is_odd_frame: bool,
+    pub dot_count: u64,
+    pub is_vblank_suppressed: bool,
+    pub is_nmi_pending: bool,
+    /// The number of frames whose rendering has been completed.
+    pub frame_count: u64,
+    pub frame: Frame,
+    next_tile_index: u8,
+    next_tile_attribute: u8,
+    next_tile_pattern_low: u8,
+    next_tile_pattern_high: u8,
+    pattern_shift_register_low: u16,
+    pattern_shift_register_high: u16,
+    attribute_shift_register_low: u16,
+    attribute_shift_register_high: u16,
+    scanline_sprites: [ScanlineSprite; 8],
+    scanline_sprite_count: u8,
+    is_sprite_zero_on_scanline: bool,
 }
 
 impl Ppu {
@@ -14,65 +60,487 @@ impl Ppu {
         Self {
             ram: [0; 0x800],
             palette_ram: [0; 0x20],
+            oam: [0; 0x100],
             control_register: 0,
+            mask_register: 0,
+            status_register: 0,
+            oam_address: 0,
+            io_latch: 0,
             read_buffer: 0,
             current_address: 0,
+            temporary_address: 0,
+            fine_x_scroll: 0,
+            write_toggle: false,
+            scanline: 0,
+            dot: 0,
+            is_odd_frame: false,
+            dot_count: 0,
+            is_vblank_suppressed: false,
+            is_nmi_pending: false,
+            frame_count: 0,
+            frame: Frame::new(),
+            next_tile_index: 0,
+            next_tile_attribute: 0,
+            next_tile_pattern_low: 0,
+            next_tile_pattern_high: 0,
+            pattern_shift_register_low: 0,
+            pattern_shift_register_high: 0,
+            attribute_shift_register_low: 0,
+            attribute_shift_register_high: 0,
+            scanline_sprites: [ScanlineSprite::default(); 8],
+            scanline_sprite_count: 0,
+            is_sprite_zero_on_scanline: false,
         }
     }
 
-    pub(super) fn write_ppuaddr<Visitor: super::Visitor>(
+    pub(super) fn read_register<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+    ) -> Visitor::U8 {
+        visitor.call_native(
+            Nes::<Cartridge>::catch_up_before_access,
+            (&raw mut *nes).cast(),
+        );
+
+        // registers are mirrored every 8 bytes
+        let address = {
+            let address_mask = visitor.immediate_u16(0x2007);
+            visitor.and_u16(address, address_mask)
+        };
+
+        // write-only registers read back the last value present on the data bus
+        let io_latch = visitor.memory_u8(&raw const nes.ppu.io_latch);
+
+        let mut if_address_in_range = |visitor: &mut Visitor,
+                                       address_range: RangeInclusive<u16>,
+                                       visit_true_block: fn(&mut Nes<Cartridge>, Visitor),
+                                       false_value: Visitor::U8|
+         -> Visitor::U8 {
+            let condition = visitor.is_in_range(address, address_range);
+
+            visitor.if_else_with_result(
+                condition,
+                |visitor| visit_true_block(nes, visitor),
+                |visitor| {
+                    visitor.terminate(Some(false_value));
+                },
+            )
+        };
+
+        let value = if_address_in_range(
+            visitor,
+            0x2002..=0x2002,
+            |nes, mut visitor| {
+                let value = nes.ppu.read_ppustatus(&mut visitor);
+                visitor.terminate(Some(value));
+            },
+            io_latch,
+        );
+        let value = if_address_in_range(
+            visitor,
+            0x2004..=0x2004,
+            |nes, mut visitor| {
+                let value = nes.ppu.read_oamdata(&mut visitor);
+                visitor.terminate(Some(value));
+            },
+            value,
+        );
+        let value = if_address_in_range(
+            visitor,
+            0x2007..=0x2007,
+            |nes, mut visitor| {
+                let value = Self::read_ppudata(nes, &mut visitor);
+                visitor.terminate(Some(value));
+            },
+            value,
+        );
+        visitor.set_memory_u8(&raw mut nes.ppu.io_latch, value);
+        value
+    }
+
+    pub(super) fn write_register<
+        Cartridge: crate::cartridge::Cartridge,
+        Visitor: super::Visitor,
+    >(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+        value: Visitor::U8,
+    ) {
+        visitor.call_native(
+            Nes::<Cartridge>::catch_up_before_access,
+            (&raw mut *nes).cast(),
+        );
+
+        // registers are mirrored every 8 bytes
+        let address = {
+            let address_mask = visitor.immediate_u16(0x2007);
+            visitor.and_u16(address, address_mask)
+        };
+
+        visitor.set_memory_u8(&raw mut nes.ppu.io_latch, value);
+
+        let mut if_address_in_range =
+            |range: RangeInclusive<u16>,
+             visit_true_block: fn(&mut Nes<Cartridge>, Visitor, Visitor::U8)| {
+                let condition = visitor.is_in_range(address, range);
+
+                visitor.r#if(condition, |visitor| {
+                    visit_true_block(nes, visitor, value);
+                });
+            };
+
+        if_address_in_range(0x2000..=0x2000, |nes, mut visitor, value| {
+            nes.ppu.write_ppuctrl(&mut visitor, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x2001..=0x2001, |nes, mut visitor, value| {
+            visitor.set_memory_u8(&raw mut nes.ppu.mask_register, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x2003..=0x2003, |nes, mut visitor, value| {
+            visitor.set_memory_u8(&raw mut nes.ppu.oam_address, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x2004..=0x2004, |nes, mut visitor, value| {
+            nes.ppu.write_oamdata(&mut visitor, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x2005..=0x2005, |nes, mut visitor, value| {
+            nes.ppu.write_ppuscroll(&mut visitor, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x2006..=0x2006, |nes, mut visitor, value| {
+            nes.ppu.write_ppuaddr(&mut visitor, value);
+            visitor.terminate(None);
+        });
+        if_address_in_range(0x2007..=0x2007, |nes, mut visitor, value| {
+            Self::write_ppudata(nes, &mut visitor, value);
+            visitor.terminate(None);
+        });
+    }
+
+    fn read_ppustatus<Visitor: super::Visitor>(&mut self, visitor: &mut Visitor) -> Visitor::U8 {
+        let status = visitor.memory_u8(&raw const self.status_register);
+        let value = {
+            let status_mask = visitor.immediate_u8(0b1110_0000);
+            let status = visitor.and_u8(status, status_mask);
+            let io_latch = visitor.memory_u8(&raw const self.io_latch);
+            let io_latch_mask = visitor.immediate_u8(0b0001_1111);
+            let io_latch = visitor.and_u8(io_latch, io_latch_mask);
+            visitor.or(status, io_latch)
+        };
+
+        // reading the status register just before the vblank flag gets set
+        // prevents it from being set for this frame, and reading it just after
+        // prevents the corresponding NMI
+        let is_vblank_start_scanline = {
+            let scanline = visitor.memory_u16(&raw const self.scanline);
+            visitor.is_in_range(scanline, 241..=241)
+        };
+        let dot = visitor.memory_u16(&raw const self.dot);
+        let is_before_vblank_start = {
+            let is_vblank_start_dot = visitor.is_in_range(dot, 1..=1);
+            visitor.and_u1(is_vblank_start_scanline, is_vblank_start_dot)
+        };
+        let is_after_vblank_start = {
+            let is_after_vblank_start_dot = visitor.is_in_range(dot, 2..=3);
+            visitor.and_u1(is_vblank_start_scanline, is_after_vblank_start_dot)
+        };
+        let is_vblank_suppressed = &raw mut self.is_vblank_suppressed;
+        visitor.r#if(is_before_vblank_start, |mut visitor| {
+            let r#true = visitor.immediate_u1(true);
+            visitor.set_memory_bool(is_vblank_suppressed, r#true);
+            visitor.terminate(None);
+        });
+        let is_nmi_pending = &raw mut self.is_nmi_pending;
+        visitor.r#if(is_after_vblank_start, |mut visitor| {
+            let r#false = visitor.immediate_u1(false);
+            visitor.set_memory_bool(is_nmi_pending, r#false);
+            visitor.terminate(None);
+        });
+
+        // reading the status register clears the vblank flag and resets the
+        // write toggle shared by PPUSCROLL and PPUADDR
+        let vblank_clear_mask = visitor.immediate_u8(0b0111_1111);
+        let status = visitor.and_u8(status, vblank_clear_mask);
+        visitor.set_memory_u8(&raw mut self.status_register, status);
+        let r#false = visitor.immediate_u1(false);
+        visitor.set_memory_bool(&raw mut self.write_toggle, r#false);
+
+        value
+    }
+
+    fn read_oamdata<Visitor: super::Visitor>(&mut self, visitor: &mut Visitor) -> Visitor::U8 {
+        let n0 = visitor.immediate_u8(0);
+
+        let oam_address = visitor.memory_u8(&raw const self.oam_address);
+        let oam_address = visitor.concatenate(n0, oam_address);
+        visitor.memory_with_offset_u8(self.oam.as_ptr(), oam_address)
+    }
+
+    pub(super) fn write_oamdata<Visitor: super::Visitor>(
+        &mut self,
+        visitor: &mut Visitor,
+        value: Visitor::U8,
+    ) {
+        let n0 = visitor.immediate_u8(0);
+        let n1 = visitor.immediate_u8(1);
+
+        let oam_address = visitor.memory_u8(&raw const self.oam_address);
+        let oam_address_u16 = visitor.concatenate(n0, oam_address);
+        visitor.set_memory_with_offset_u8(self.oam.as_mut_ptr(), oam_address_u16, value);
+
+        let incremented_oam_address = visitor.add_u8(oam_address, n1);
+        visitor.set_memory_u8(&raw mut self.oam_address, incremented_oam_address);
+    }
+
+    fn write_ppuctrl<Visitor: super::Visitor>(
+        &mut self,
+        visitor: &mut Visitor,
+        value: Visitor::U8,
+    ) {
+        // enabling NMIs during vblank immediately generates one
+        let is_nmi_edge = {
+            let old_control_register = visitor.memory_u8(&raw const self.control_register);
+            let was_nmi_enabled = visitor.get_bit(old_control_register, 7);
+            let was_nmi_disabled = visitor.not(was_nmi_enabled);
+            let is_nmi_enabled = visitor.get_bit(value, 7);
+            let status_register = visitor.memory_u8(&raw const self.status_register);
+            let is_vblank = visitor.get_bit(status_register, 7);
+            let is_nmi_output = visitor.and_u1(is_nmi_enabled, is_vblank);
+            visitor.and_u1(was_nmi_disabled, is_nmi_output)
+        };
+        let is_nmi_pending = &raw mut self.is_nmi_pending;
+        visitor.r#if(is_nmi_edge, |mut visitor| {
+            let r#true = visitor.immediate_u1(true);
+            visitor.set_memory_bool(is_nmi_pending, r#true);
+            visitor.terminate(None);
+        });
+
+        visitor.set_memory_u8(&raw mut self.control_register, value);
+
+        // t: ...GH.. ........ <- d: ......GH
+        let temporary_address = visitor.memory_u16(&raw const self.temporary_address);
+        let temporary_address_low = visitor.low_byte(temporary_address);
+        let temporary_address_high = {
+            let mask = visitor.immediate_u8(0b0111_0011);
+            let temporary_address_high = visitor.high_byte(temporary_address);
+            let temporary_address_high = visitor.and_u8(temporary_address_high, mask);
+
+            let nametable_mask = visitor.immediate_u8(0b11);
+            let nametable = visitor.and_u8(value, nametable_mask);
+            let nametable = visitor.shift_left(nametable);
+            let nametable = visitor.shift_left(nametable);
+            visitor.or(temporary_address_high, nametable)
+        };
+        let temporary_address = visitor.concatenate(temporary_address_high, temporary_address_low);
+        visitor.set_memory_u16(&raw mut self.temporary_address, temporary_address);
+    }
+
+    fn write_ppuscroll<Visitor: super::Visitor>(
+        &mut self,
+        visitor: &mut Visitor,
+        value: Visitor::U8,
+    ) {
+        let temporary_address = &raw mut self.temporary_address;
+        let fine_x_scroll = &raw mut self.fine_x_scroll;
+
+        let write_toggle = visitor.memory_bool(&raw const self.write_toggle);
+        visitor.if_else(
+            write_toggle,
+            |mut visitor| {
+                // t: FGH..AB CDE..... <- d: ABCDEFGH
+                let old_temporary_address = visitor.memory_u16(temporary_address.cast_const());
+                let temporary_address_low = {
+                    let mask = visitor.immediate_u8(0b0001_1111);
+                    let old_low = visitor.low_byte(old_temporary_address);
+                    let old_low = visitor.and_u8(old_low, mask);
+
+                    let coarse_y_mask = visitor.immediate_u8(0b0011_1000);
+                    let coarse_y = visitor.and_u8(value, coarse_y_mask);
+                    let coarse_y = visitor.shift_left(coarse_y);
+                    let coarse_y = visitor.shift_left(coarse_y);
+                    visitor.or(old_low, coarse_y)
+                };
+                let temporary_address_high = {
+                    let mask = visitor.immediate_u8(0b0000_1100);
+                    let old_high = visitor.high_byte(old_temporary_address);
+                    let old_high = visitor.and_u8(old_high, mask);
+
+                    let fine_y_mask = visitor.immediate_u8(0b0000_0111);
+                    let mut fine_y = visitor.and_u8(value, fine_y_mask);
+                    for _ in 0..4 {
+                        fine_y = visitor.shift_left(fine_y);
+                    }
+
+                    let mut coarse_y = value;
+                    for _ in 0..6 {
+                        coarse_y = visitor.shift_right(coarse_y);
+                    }
+
+                    let high = visitor.or(old_high, fine_y);
+                    visitor.or(high, coarse_y)
+                };
+                let new_temporary_address =
+                    visitor.concatenate(temporary_address_high, temporary_address_low);
+                visitor.set_memory_u16(temporary_address, new_temporary_address);
+                visitor.terminate(None);
+            },
+            |mut visitor| {
+                // t: ....... ...ABCDE <- d: ABCDE...
+                // x:              FGH <- d: .....FGH
+                let old_temporary_address = visitor.memory_u16(temporary_address.cast_const());
+                let temporary_address_high = visitor.high_byte(old_temporary_address);
+                let temporary_address_low = {
+                    let mask = visitor.immediate_u8(0b1110_0000);
+                    let old_low = visitor.low_byte(old_temporary_address);
+                    let old_low = visitor.and_u8(old_low, mask);
+
+                    let mut coarse_x = value;
+                    for _ in 0..3 {
+                        coarse_x = visitor.shift_right(coarse_x);
+                    }
+                    visitor.or(old_low, coarse_x)
+                };
+                let new_temporary_address =
+                    visitor.concatenate(temporary_address_high, temporary_address_low);
+                visitor.set_memory_u16(temporary_address, new_temporary_address);
+
+                let fine_x_mask = visitor.immediate_u8(0b0000_0111);
+                let fine_x = visitor.and_u8(value, fine_x_mask);
+                visitor.set_memory_u8(fine_x_scroll, fine_x);
+                visitor.terminate(None);
+            },
+        );
+        let write_toggle = visitor.not(write_toggle);
+        visitor.set_memory_bool(&raw mut self.write_toggle, write_toggle);
+    }
+
+    fn write_ppuaddr<Visitor: super::Visitor>(
         &mut self,
         visitor: &mut Visitor,
         value: Visitor::U8,
     ) {
-        let old_address = visitor.memory_u16(&raw const self.current_address);
-        let new_address_high = visitor.low_byte(old_address);
-        let new_address_low = value;
-        let new_address = visitor.concatenate(new_address_high, new_address_low);
-        visitor.set_memory_u16(&raw mut self.current_address, new_address);
+        let temporary_address = &raw mut self.temporary_address;
+        let current_address = &raw mut self.current_address;
+
+        let write_toggle = visitor.memory_bool(&raw const self.write_toggle);
+        visitor.if_else(
+            write_toggle,
+            |mut visitor| {
+                // t: ....... ABCDEFGH <- d: ABCDEFGH
+                // v: <...all bits...> <- t: <...all bits...>
+                let old_temporary_address = visitor.memory_u16(temporary_address.cast_const());
+                let temporary_address_high = visitor.high_byte(old_temporary_address);
+                let new_temporary_address = visitor.concatenate(temporary_address_high, value);
+                visitor.set_memory_u16(temporary_address, new_temporary_address);
+                visitor.set_memory_u16(current_address, new_temporary_address);
+                visitor.terminate(None);
+            },
+            |mut visitor| {
+                // t: .CDEFGH ........ <- d: ..CDEFGH
+                //        <unused>     <- d: AB......
+                // t: Z...... ........ <- 0 (bit Z is cleared)
+                let old_temporary_address = visitor.memory_u16(temporary_address.cast_const());
+                let temporary_address_low = visitor.low_byte(old_temporary_address);
+                let mask = visitor.immediate_u8(0b0011_1111);
+                let temporary_address_high = visitor.and_u8(value, mask);
+                let new_temporary_address =
+                    visitor.concatenate(temporary_address_high, temporary_address_low);
+                visitor.set_memory_u16(temporary_address, new_temporary_address);
+                visitor.terminate(None);
+            },
+        );
+        let write_toggle = visitor.not(write_toggle);
+        visitor.set_memory_bool(&raw mut self.write_toggle, write_toggle);
     }
 
-    pub(super) fn read_ppudata<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+    fn read_ppudata<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
         nes: &mut Nes<Cartridge>,
         visitor: &mut Visitor,
     ) -> Visitor::U8 {
-        let address = visitor.memory_u16(&raw const nes.ppu.current_address);
+        let address = nes.ppu.current_vram_address(visitor);
         nes.ppu.increment_ppu_current_address(visitor);
         Self::read(nes, visitor, address)
     }
 
-    pub(super) fn write_ppudata<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+    fn write_ppudata<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
         nes: &mut Nes<Cartridge>,
         visitor: &mut Visitor,
         value: Visitor::U8,
     ) {
-        let address = visitor.memory_u16(&raw const nes.ppu.current_address);
+        let address = nes.ppu.current_vram_address(visitor);
         nes.ppu.increment_ppu_current_address(visitor);
         Self::write(nes, visitor, address, value);
     }
 
+    fn current_vram_address<Visitor: super::Visitor>(&self, visitor: &mut Visitor) -> Visitor::U16 {
+        // the PPU address bus is only 14 bits wide
+        let mask = visitor.immediate_u16(0x3fff);
+        let address = visitor.memory_u16(&raw const self.current_address);
+        visitor.and_u16(address, mask)
+    }
+
     fn increment_ppu_current_address<Visitor: super::Visitor>(&mut self, visitor: &mut Visitor) {
-        let n0 = visitor.immediate_u8(0);
+        let is_rendering = {
+            let mask_register = visitor.memory_u8(&raw const self.mask_register);
+            let rendering_mask = visitor.immediate_u8(0b0001_1000);
+            let rendering_bits = visitor.and_u8(mask_register, rendering_mask);
+            let is_rendering_disabled = visitor.is_zero(rendering_bits);
+            let is_rendering_enabled = visitor.not(is_rendering_disabled);
 
-        let address = visitor.memory_u16(&raw const self.current_address);
-        let address_increment = {
-            let control_register = visitor.memory_u8(&raw const self.control_register);
-            let control_register_increment_bit = visitor.get_bit(control_register, 2);
-            let increment = visitor.if_else_with_result(
-                control_register_increment_bit,
-                |mut visitor| {
-                    let n32 = visitor.immediate_u8(32);
-                    visitor.terminate(Some(n32));
-                },
-                |mut visitor| {
-                    let n1 = visitor.immediate_u8(1);
-                    visitor.terminate(Some(n1));
-                },
-            );
-            visitor.concatenate(n0, increment)
+            let scanline = visitor.memory_u16(&raw const self.scanline);
+            let is_idle_scanline = visitor.is_in_range(scanline, 240..=260);
+            let is_rendering_scanline = visitor.not(is_idle_scanline);
+
+            visitor.and_u1(is_rendering_enabled, is_rendering_scanline)
         };
-        let incremented_address = visitor.add_u16(address, address_increment);
-        visitor.set_memory_u16(&raw mut self.current_address, incremented_address);
+
+        let ppu = (&raw mut *self).cast();
+        let current_address = &raw mut self.current_address;
+        let control_register = &raw const self.control_register;
+        visitor.if_else(
+            is_rendering,
+            |mut visitor| {
+                visitor.call_native(Self::increment_current_address_during_rendering, ppu);
+                visitor.terminate(None);
+            },
+            |mut visitor| {
+                let n0 = visitor.immediate_u8(0);
+
+                let address = visitor.memory_u16(current_address.cast_const());
+                let address_increment = {
+                    let control_register = visitor.memory_u8(control_register);
+                    let control_register_increment_bit = visitor.get_bit(control_register, 2);
+                    let increment = visitor.if_else_with_result(
+                        control_register_increment_bit,
+                        |mut visitor| {
+                            let n32 = visitor.immediate_u8(32);
+                            visitor.terminate(Some(n32));
+                        },
+                        |mut visitor| {
+                            let n1 = visitor.immediate_u8(1);
+                            visitor.terminate(Some(n1));
+                        },
+                    );
+                    visitor.concatenate(n0, increment)
+                };
+                let incremented_address = visitor.add_u16(address, address_increment);
+                visitor.set_memory_u16(current_address, incremented_address);
+                visitor.terminate(None);
+            },
+        );
+    }
+
+    /// Performs the glitched increment of PPUDATA accesses made while
+    /// rendering, which increments both the coarse X and Y scroll at once.
+    #[expect(clippy::cast_ptr_alignment)]
+    unsafe extern "C" fn increment_current_address_during_rendering(ppu: *mut u8) {
+        let ppu = unsafe { &mut *ppu.cast::<Self>() };
+        ppu.increment_coarse_x_scroll();
+        ppu.increment_y_scroll();
     }
 
     fn read<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
@@ -86,17 +554,7 @@ impl Ppu {
              visit_true_block: fn(&mut Nes<Cartridge>, Visitor, Visitor::U16),
              false_value: Visitor::U8|
              -> Visitor::U8 {
-                let condition = {
-                    let lower_bound_condition = {
-                        let start = visitor.immediate_u16(*address_range.start());
-                        visitor.less_than_or_equal(start, address)
-                    };
-                    let upper_bound_condition = {
-                        let end = visitor.immediate_u16(*address_range.end());
-                        visitor.less_than_or_equal(address, end)
-                    };
-                    visitor.and_u1(lower_bound_condition, upper_bound_condition)
-                };
+                let condition = visitor.is_in_range(address, address_range);
 
                 visitor.if_else_with_result(
                     condition,
@@ -108,43 +566,24 @@ impl Ppu {
             };
 
         let value = visitor.immediate_u8(0);
+        let value = if_address_in_range(
+            visitor,
+            0x0000..=0x1fff,
+            |nes, mut visitor, address| {
+                let previous_value = visitor.memory_u8(&raw const nes.ppu.read_buffer);
+                let value = nes.cartridge.read_chr(&mut visitor, address);
+                visitor.set_memory_u8(&raw mut nes.ppu.read_buffer, value);
+                visitor.terminate(Some(previous_value));
+            },
+            value,
+        );
         let value = if_address_in_range(
             visitor,
             0x2000..=0x3eff,
             |nes, mut visitor, address| {
                 let previous_value = visitor.memory_u8(&raw const nes.ppu.read_buffer);
-                let address = {
-                    let is_mirroring_horizontal =
-                        nes.cartridge.read_is_mirroring_horizontal(&mut visitor);
-
-                    // TODO: simplify this logic once if_else_with_result can return a U16
-                    let address_low = visitor.low_byte(address);
-                    let address_high = visitor.if_else_with_result(
-                        is_mirroring_horizontal,
-                        |mut visitor| {
-                            let address_high = visitor.high_byte(address);
-                            let address_high_low = {
-                                let mask = visitor.immediate_u8(0b0000_0111);
-                                visitor.and_u8(address_high, mask)
-                            };
-                            let address_high_high = {
-                                let mask = visitor.immediate_u8(0b0001_0000);
-                                let address = visitor.and_u8(address_high, mask);
-                                visitor.shift_right(address)
-                            };
-                            let address_high = visitor.or(address_high_high, address_high_low);
-                            visitor.terminate(Some(address_high));
-                        },
-                        |mut visitor| {
-                            let address_high = visitor.high_byte(address);
-                            visitor.terminate(Some(address_high));
-                        },
-                    );
-                    let address = visitor.concatenate(address_high, address_low);
-                    let mask = visitor.immediate_u16(0x7ff);
-                    visitor.and_u16(address, mask)
-                };
-                let value = visitor.memory_with_offset_u8(nes.ppu.ram.as_ptr(), address);
+                let offset = Self::nametable_ram_offset(nes, &mut visitor, address);
+                let value = visitor.memory_with_offset_u8(nes.ppu.ram.as_ptr(), offset);
                 visitor.set_memory_u8(&raw mut nes.ppu.read_buffer, value);
                 visitor.terminate(Some(previous_value));
             },
@@ -154,9 +593,28 @@ impl Ppu {
             visitor,
             0x3f00..=0x3fff,
             |nes, mut visitor, address| {
-                let address_mask = visitor.immediate_u16(0x1f);
-                let address = visitor.and_u16(address, address_mask);
-                let value = visitor.memory_with_offset_u8(nes.ppu.palette_ram.as_ptr(), address);
+                // palette reads are not buffered, but still fill the read
+                // buffer with the nametable byte "underneath" the palette
+                let nametable_address = {
+                    let mask = visitor.immediate_u16(0x2fff);
+                    visitor.and_u16(address, mask)
+                };
+                let nametable_offset =
+                    Self::nametable_ram_offset(nes, &mut visitor, nametable_address);
+                let nametable_value =
+                    visitor.memory_with_offset_u8(nes.ppu.ram.as_ptr(), nametable_offset);
+                visitor.set_memory_u8(&raw mut nes.ppu.read_buffer, nametable_value);
+
+                // palette entries are 6 bits wide, with the remaining bits
+                // coming from the data bus
+                let offset = Self::palette_ram_offset(&mut visitor, address);
+                let value = visitor.memory_with_offset_u8(nes.ppu.palette_ram.as_ptr(), offset);
+                let value_mask = visitor.immediate_u8(0b0011_1111);
+                let value = visitor.and_u8(value, value_mask);
+                let io_latch = visitor.memory_u8(&raw const nes.ppu.io_latch);
+                let io_latch_mask = visitor.immediate_u8(0b1100_0000);
+                let io_latch = visitor.and_u8(io_latch, io_latch_mask);
+                let value = visitor.or(value, io_latch);
                 visitor.terminate(Some(value));
             },
             value,
@@ -176,66 +634,440 @@ impl Ppu {
             Visitor::U16,
             Visitor::U8,
         )| {
-            let condition = {
-                let lower_bound_condition = {
-                    let start = visitor.immediate_u16(*range.start());
-                    visitor.less_than_or_equal(start, address)
-                };
-                let upper_bound_condition = {
-                    let end = visitor.immediate_u16(*range.end());
-                    visitor.less_than_or_equal(address, end)
-                };
-                visitor.and_u1(lower_bound_condition, upper_bound_condition)
-            };
+            let condition = visitor.is_in_range(address, range);
 
             visitor.r#if(condition, |visitor| {
                 visit_true_block(nes, visitor, address, value);
             });
         };
 
+        if_address_in_range(0x0000..=0x1fff, |nes, mut visitor, address, value| {
+            nes.cartridge.write_chr(&mut visitor, address, value);
+            visitor.terminate(None);
+        });
         if_address_in_range(0x2000..=0x3eff, |nes, mut visitor, address, value| {
-            let address = {
-                let is_mirroring_horizontal =
-                    nes.cartridge.read_is_mirroring_horizontal(&mut visitor);
-
-                // TODO: simplify this logic once if_else_with_result can return a U16
-                let address_low = visitor.low_byte(address);
-                let address_high = visitor.if_else_with_result(
-                    is_mirroring_horizontal,
-                    |mut visitor| {
-                        let address_high = visitor.high_byte(address);
-                        let address_high_low = {
-                            let mask = visitor.immediate_u8(0b111);
-                            visitor.and_u8(address_high, mask)
-                        };
-                        let address_high_high = {
-                            let mask = visitor.immediate_u8(0b0001_0000);
-                            let address = visitor.and_u8(address_high, mask);
-                            visitor.shift_right(address)
-                        };
-                        let address_high = visitor.or(address_high_high, address_high_low);
-                        visitor.terminate(Some(address_high));
-                    },
-                    |mut visitor| {
-                        let address_high = visitor.high_byte(address);
-                        visitor.terminate(Some(address_high));
-                    },
-                );
-                let address = visitor.concatenate(address_high, address_low);
-
-                let mask = visitor.immediate_u16(0x7ff);
-                visitor.and_u16(address, mask)
-            };
-            visitor.set_memory_with_offset_u8(nes.ppu.ram.as_mut_ptr(), address, value);
+            let offset = Self::nametable_ram_offset(nes, &mut visitor, address);
+            visitor.set_memory_with_offset_u8(nes.ppu.ram.as_mut_ptr(), offset, value);
             visitor.terminate(None);
         });
         if_address_in_range(0x3f00..=0x3fff, |nes, mut visitor, address, value| {
-            let address_mask = visitor.immediate_u16(0x1f);
-            let address = visitor.and_u16(address, address_mask);
-            visitor.set_memory_with_offset_u8(nes.ppu.palette_ram.as_mut_ptr(), address, value);
+            let offset = Self::palette_ram_offset(&mut visitor, address);
+            visitor.set_memory_with_offset_u8(nes.ppu.palette_ram.as_mut_ptr(), offset, value);
             visitor.terminate(None);
         });
     }
+
+    fn nametable_ram_offset<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &Nes<Cartridge>,
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+    ) -> Visitor::U16 {
+        // the flag corresponds to bit 0 of the iNES header, which selects
+        // between nametable bits A10 and A11
+        let is_mirroring_horizontal = nes.cartridge.read_is_mirroring_horizontal(visitor);
+
+        // TODO: simplify this logic once if_else_with_result can return a U16
+        let address_low = visitor.low_byte(address);
+        let address_high = visitor.if_else_with_result(
+            is_mirroring_horizontal,
+            |mut visitor| {
+                let address_high = visitor.high_byte(address);
+                let mask = visitor.immediate_u8(0b0000_0111);
+                let address_high = visitor.and_u8(address_high, mask);
+                visitor.terminate(Some(address_high));
+            },
+            |mut visitor| {
+                let address_high = visitor.high_byte(address);
+                let address_high_low = {
+                    let mask = visitor.immediate_u8(0b0000_0011);
+                    visitor.and_u8(address_high, mask)
+                };
+                let address_high_high = {
+                    let mask = visitor.immediate_u8(0b0000_1000);
+                    let address = visitor.and_u8(address_high, mask);
+                    visitor.shift_right(address)
+                };
+                let address_high = visitor.or(address_high_high, address_high_low);
+                visitor.terminate(Some(address_high));
+            },
+        );
+        visitor.concatenate(address_high, address_low)
+    }
+
+    fn palette_ram_offset<Visitor: super::Visitor>(
+        visitor: &mut Visitor,
+        address: Visitor::U16,
+    ) -> Visitor::U16 {
+        let offset_mask = visitor.immediate_u16(0x1f);
+        let offset = visitor.and_u16(address, offset_mask);
+
+        // the backdrop entries of the sprite palettes ($3F10/$3F14/$3F18/$3F1C)
+        // mirror those of the background palettes ($3F00/$3F04/$3F08/$3F0C)
+        let is_mirrored = {
+            let mirror_mask = visitor.immediate_u16(0x13);
+            let mirror_bits = visitor.and_u16(offset, mirror_mask);
+            visitor.is_in_range(mirror_bits, 0x10..=0x10)
+        };
+        let mirrored_offset = {
+            let mask = visitor.immediate_u16(0x0f);
+            visitor.and_u16(offset, mask)
+        };
+        visitor.select(is_mirrored, mirrored_offset, offset)
+    }
+
+    /// Advances the PPU by a single dot.
+    pub(super) fn step(&mut self, cartridge: &impl crate::cartridge::Cartridge) {
+        let is_rendering_enabled = self.mask_register & 0b0001_1000 != 0;
+
+        match (self.scanline, self.dot) {
+            (241, 1) => {
+                self.frame_count += 1;
+                if !self.is_vblank_suppressed {
+                    self.status_register |= 0b1000_0000;
+                    if self.control_register & 0b1000_0000 != 0 {
+                        self.is_nmi_pending = true;
+                    }
+                }
+            }
+            (261, 1) => {
+                self.status_register &= 0b0001_1111;
+                self.is_vblank_suppressed = false;
+            }
+            _ => {}
+        }
+
+        if is_rendering_enabled && (self.scanline < 240 || self.scanline == 261) {
+            self.fetch_background(cartridge);
+            if self.dot == 257 {
+                self.evaluate_sprites(cartridge);
+            }
+        }
+        if self.scanline < 240 && (1..=256).contains(&self.dot) {
+            self.output_pixel(is_rendering_enabled);
+        }
+        if is_rendering_enabled && (self.scanline < 240 || self.scanline == 261) {
+            self.update_current_address_for_rendering();
+        }
+
+        self.dot += 1;
+        if self.scanline == 261 && self.dot == 340 && self.is_odd_frame && is_rendering_enabled {
+            // the last dot of the pre-render scanline is skipped on odd frames
+            self.dot += 1;
+        }
+        if self.dot == 341 {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == 262 {
+                self.scanline = 0;
+                self.is_odd_frame = !self.is_odd_frame;
+            }
+        }
+        self.dot_count += 1;
+    }
+
+    fn fetch_background(&mut self, cartridge: &impl crate::cartridge::Cartridge) {
+        if !matches!(self.dot, 2..=257 | 321..=337) {
+            return;
+        }
+
+        if self.mask_register & 0b0000_1000 != 0 {
+            self.pattern_shift_register_low <<= 1;
+            self.pattern_shift_register_high <<= 1;
+            self.attribute_shift_register_low <<= 1;
+            self.attribute_shift_register_high <<= 1;
+        }
+
+        match (self.dot - 1) % 8 {
+            0 => {
+                self.load_background_shift_registers();
+                self.next_tile_index =
+                    self.peek(cartridge, 0x2000 | (self.current_address & 0x0fff));
+            }
+            2 => {
+                // each attribute byte covers a 4x4 tile area, with 2 bits for
+                // each 2x2 tile quadrant
+                let attribute_address = 0x23c0
+                    | (self.current_address & 0x0c00)
+                    | ((self.current_address >> 4) & 0x38)
+                    | ((self.current_address >> 2) & 0x07);
+                let attribute = self.peek(cartridge, attribute_address);
+                let shift = ((self.current_address >> 4) & 0b100) | (self.current_address & 0b10);
+                self.next_tile_attribute = (attribute >> shift) & 0b11;
+            }
+            4 => {
+                let address = self.background_pattern_address();
+                self.next_tile_pattern_low = self.peek(cartridge, address);
+            }
+            6 => {
+                let address = self.background_pattern_address() + 8;
+                self.next_tile_pattern_high = self.peek(cartridge, address);
+            }
+            _ => {}
+        }
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        let pattern_table = u16::from(self.control_register & 0b0001_0000) << 8;
+        let fine_y = self.current_address >> 12;
+        pattern_table | (u16::from(self.next_tile_index) << 4) | fine_y
+    }
+
+    fn load_background_shift_registers(&mut self) {
+        let spread_bit = |value: u8, bit_index: u8| {
+            if (value >> bit_index) & 1 != 0 {
+                0xff
+            } else {
+                0x00
+            }
+        };
+
+        self.pattern_shift_register_low =
+            (self.pattern_shift_register_low & 0xff00) | u16::from(self.next_tile_pattern_low);
+        self.pattern_shift_register_high =
+            (self.pattern_shift_register_high & 0xff00) | u16::from(self.next_tile_pattern_high);
+        self.attribute_shift_register_low =
+            (self.attribute_shift_register_low & 0xff00) | spread_bit(self.next_tile_attribute, 0);
+        self.attribute_shift_register_high =
+            (self.attribute_shift_register_high & 0xff00) | spread_bit(self.next_tile_attribute, 1);
+    }
+
+    /// Selects the sprites to be drawn on the next scanline.
+    fn evaluate_sprites(&mut self, cartridge: &impl crate::cartridge::Cartridge) {
+        self.scanline_sprite_count = 0;
+        self.is_sprite_zero_on_scanline = false;
+
+        // no sprites are drawn on the first scanline
+        if self.scanline == 261 {
+            return;
+        }
+
+        let sprite_height = if self.control_register & 0b0010_0000 != 0 {
+            16
+        } else {
+            8
+        };
+
+        let scanline = self.scanline;
+        let get_row = |y: u8| {
+            scanline
+                .checked_sub(u16::from(y))
+                .filter(|row| *row < sprite_height)
+        };
+
+        let mut sprite_index = 0;
+        while sprite_index < 64 && self.scanline_sprite_count < 8 {
+            let [y, tile_index, attributes, x] =
+                self.oam[sprite_index * 4..][..4].try_into().unwrap();
+            sprite_index += 1;
+
+            let Some(row) = get_row(y) else {
+                continue;
+            };
+            if sprite_index == 1 {
+                self.is_sprite_zero_on_scanline = true;
+            }
+
+            let row = if attributes & 0b1000_0000 != 0 {
+                sprite_height - 1 - row
+            } else {
+                row
+            };
+            let pattern_address = if sprite_height == 16 {
+                let pattern_table = u16::from(tile_index & 1) << 12;
+                let tile_index = u16::from(tile_index & !1) + (row >> 3);
+                pattern_table | (tile_index << 4) | (row & 0b111)
+            } else {
+                let pattern_table = u16::from(self.control_register & 0b0000_1000) << 9;
+                pattern_table | (u16::from(tile_index) << 4) | row
+            };
+            let mut pattern_low = self.peek(cartridge, pattern_address);
+            let mut pattern_high = self.peek(cartridge, pattern_address + 8);
+            if attributes & 0b0100_0000 != 0 {
+                pattern_low = pattern_low.reverse_bits();
+                pattern_high = pattern_high.reverse_bits();
+            }
+
+            self.scanline_sprites[usize::from(self.scanline_sprite_count)] = ScanlineSprite {
+                x,
+                attributes,
+                pattern_low,
+                pattern_high,
+            };
+            self.scanline_sprite_count += 1;
+        }
+
+        // once eight sprites have been found, the hardware erroneously
+        // increments the byte offset along with the sprite index, scanning
+        // OAM diagonally for further sprites
+        let mut byte_offset = 0;
+        while sprite_index < 64 {
+            if get_row(self.oam[sprite_index * 4 + byte_offset]).is_some() {
+                self.status_register |= 0b0010_0000;
+                break;
+            }
+            sprite_index += 1;
+            byte_offset = (byte_offset + 1) % 4;
+        }
+    }
+
+    fn output_pixel(&mut self, is_rendering_enabled: bool) {
+        let x = self.dot - 1;
+        let is_left_column = x < 8;
+
+        let mut palette_address = 0;
+        if is_rendering_enabled {
+            let is_background_shown = self.mask_register & 0b0000_1000 != 0
+                && (!is_left_column || self.mask_register & 0b0000_0010 != 0);
+            let (background_pixel, background_palette) = if is_background_shown {
+                let bit_index = 15 - self.fine_x_scroll;
+                let get_bits = |low: u16, high: u16| {
+                    let low = u8::from((low >> bit_index) & 1 != 0);
+                    let high = u8::from((high >> bit_index) & 1 != 0);
+                    (high << 1) | low
+                };
+                (
+                    get_bits(
+                        self.pattern_shift_register_low,
+                        self.pattern_shift_register_high,
+                    ),
+                    get_bits(
+                        self.attribute_shift_register_low,
+                        self.attribute_shift_register_high,
+                    ),
+                )
+            } else {
+                (0, 0)
+            };
+
+            let are_sprites_shown = self.mask_register & 0b0001_0000 != 0
+                && (!is_left_column || self.mask_register & 0b0000_0100 != 0);
+            let sprite = are_sprites_shown
+                .then(|| {
+                    self.scanline_sprites[..usize::from(self.scanline_sprite_count)]
+                        .iter()
+                        .enumerate()
+                        .find_map(|(sprite_index, sprite)| {
+                            let column = x.checked_sub(u16::from(sprite.x))?;
+                            if column >= 8 {
+                                return None;
+                            }
+                            let bit_index = 7 - column;
+                            let low = (sprite.pattern_low >> bit_index) & 1;
+                            let high = (sprite.pattern_high >> bit_index) & 1;
+                            let pixel = (high << 1) | low;
+                            let is_sprite_zero =
+                                sprite_index == 0 && self.is_sprite_zero_on_scanline;
+                            (pixel != 0).then_some((pixel, sprite.attributes, is_sprite_zero))
+                        })
+                })
+                .flatten();
+
+            if let Some((_, _, true)) = sprite
+                && background_pixel != 0
+                && x != 255
+            {
+                // sprite 0 hit
+                self.status_register |= 0b0100_0000;
+            }
+
+            palette_address = match (background_pixel, sprite) {
+                (0, None) => 0,
+                (_, Some((sprite_pixel, sprite_attributes, _)))
+                    if background_pixel == 0 || sprite_attributes & 0b0010_0000 == 0 =>
+                {
+                    0x10 | ((sprite_attributes & 0b11) << 2) | sprite_pixel
+                }
+                _ => (background_palette << 2) | background_pixel,
+            };
+        }
+
+        let mut palette_index = self.palette_ram[usize::from(palette_address)] & 0x3f;
+        if self.mask_register & 0b0000_0001 != 0 {
+            // greyscale
+            palette_index &= 0x30;
+        }
+        let emphasis = u16::from(self.mask_register & 0b1110_0000) << 1;
+        self.frame.set_pixel(
+            usize::from(x),
+            usize::from(self.scanline),
+            u16::from(palette_index) | emphasis,
+        );
+    }
+
+    /// Reads from the PPU address space without any side effects.
+    fn peek(&self, cartridge: &impl crate::cartridge::Cartridge, address: u16) -> u8 {
+        match address & 0x3fff {
+            address @ 0x0000..0x2000 => cartridge.peek_chr(address),
+            address @ 0x2000..0x3f00 => {
+                // the flag corresponds to bit 0 of the iNES header, which
+                // selects between nametable bits A11 and A10
+                let address = if cartridge.peek_is_mirroring_horizontal() {
+                    address & 0x7ff
+                } else {
+                    ((address >> 1) & 0x400) | (address & 0x3ff)
+                };
+                self.ram[usize::from(address)]
+            }
+            address => {
+                let address = if address & 0x13 == 0x10 {
+                    address & 0x0f
+                } else {
+                    address & 0x1f
+                };
+                self.palette_ram[usize::from(address)]
+            }
+        }
+    }
+
+    fn update_current_address_for_rendering(&mut self) {
+        match self.dot {
+            8..=256 | 328..=336 if self.dot.is_multiple_of(8) => {
+                self.increment_coarse_x_scroll();
+                if self.dot == 256 {
+                    self.increment_y_scroll();
+                }
+            }
+            257 => {
+                // v: ....A.. ...BCDEF <- t: ....A.. ...BCDEF
+                self.current_address =
+                    (self.current_address & !0x041f) | (self.temporary_address & 0x041f);
+            }
+            280..=304 if self.scanline == 261 => {
+                // v: GHIA.BC DEF..... <- t: GHIA.BC DEF.....
+                self.current_address =
+                    (self.current_address & !0x7be0) | (self.temporary_address & 0x7be0);
+            }
+            _ => {}
+        }
+    }
+
+    fn increment_coarse_x_scroll(&mut self) {
+        if self.current_address & 0x001f == 31 {
+            self.current_address &= !0x001f;
+            self.current_address ^= 0x0400;
+        } else {
+            self.current_address += 1;
+        }
+    }
+
+    fn increment_y_scroll(&mut self) {
+        if self.current_address & 0x7000 != 0x7000 {
+            self.current_address += 0x1000;
+            return;
+        }
+
+        self.current_address &= !0x7000;
+        let mut coarse_y = (self.current_address & 0x03e0) >> 5;
+        match coarse_y {
+            29 => {
+                coarse_y = 0;
+                self.current_address ^= 0x0800;
+            }
+            31 => coarse_y = 0,
+            _ => coarse_y += 1,
+        }
+        self.current_address = (self.current_address & !0x03e0) | (coarse_y << 5);
+    }
 }
 
 impl Default for Ppu {
@@ -243,3 +1075,572 @@ impl Default for Ppu {
         Self::new()
     }
 }
+
+impl SaveState for ScanlineSprite {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.x.save_state(writer);
+        self.attributes.save_state(writer);
+        self.pattern_low.save_state(writer);
+        self.pattern_high.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.x.load_state(reader)?;
+        self.attributes.load_state(reader)?;
+        self.pattern_low.load_state(reader)?;
+        self.pattern_high.load_state(reader)?;
+        Ok(())
+    }
+}
+
+impl Ppu {
+    /// Like [`SaveState::save_state`], but leaving out the picture.
+    pub(super) fn save_state_without_frame(&self, writer: &mut StateWriter) {
+        self.ram.save_state(writer);
+        self.palette_ram.save_state(writer);
+        self.oam.save_state(writer);
+        self.control_register.save_state(writer);
+        self.mask_register.save_state(writer);
+        self.status_register.save_state(writer);
+        self.oam_address.save_state(writer);
+        self.io_latch.save_state(writer);
+        self.read_buffer.save_state(writer);
+        self.current_address.save_state(writer);
+        self.temporary_address.save_state(writer);
+        self.fine_x_scroll.save_state(writer);
+        self.write_toggle.save_state(writer);
+        self.scanline.save_state(writer);
+        self.dot.save_state(writer);
+        self.is_odd_frame.save_state(writer);
+        self.dot_count.save_state(writer);
+        self.is_vblank_suppressed.save_state(writer);
+        self.is_nmi_pending.save_state(writer);
+        self.frame_count.save_state(writer);
+        self.next_tile_index.save_state(writer);
+        self.next_tile_attribute.save_state(writer);
+        self.next_tile_pattern_low.save_state(writer);
+        self.next_tile_pattern_high.save_state(writer);
+        self.pattern_shift_register_low.save_state(writer);
+        self.pattern_shift_register_high.save_state(writer);
+        self.attribute_shift_register_low.save_state(writer);
+        self.attribute_shift_register_high.save_state(writer);
+        self.scanline_sprites.save_state(writer);
+        self.scanline_sprite_count.save_state(writer);
+        self.is_sprite_zero_on_scanline.save_state(writer);
+    }
+
+    pub(super) fn load_state_without_frame(
+        &mut self,
+        reader: &mut StateReader<'_>,
+    ) -> Result<(), LoadStateError> {
+        self.ram.load_state(reader)?;
+        self.palette_ram.load_state(reader)?;
+        self.oam.load_state(reader)?;
+        self.control_register.load_state(reader)?;
+        self.mask_register.load_state(reader)?;
+        self.status_register.load_state(reader)?;
+        self.oam_address.load_state(reader)?;
+        self.io_latch.load_state(reader)?;
+        self.read_buffer.load_state(reader)?;
+        self.current_address.load_state(reader)?;
+        self.temporary_address.load_state(reader)?;
+        self.fine_x_scroll.load_state(reader)?;
+        self.write_toggle.load_state(reader)?;
+        self.scanline.load_state(reader)?;
+        self.dot.load_state(reader)?;
+        self.is_odd_frame.load_state(reader)?;
+        self.dot_count.load_state(reader)?;
+        self.is_vblank_suppressed.load_state(reader)?;
+        self.is_nmi_pending.load_state(reader)?;
+        self.frame_count.load_state(reader)?;
+        self.next_tile_index.load_state(reader)?;
+        self.next_tile_attribute.load_state(reader)?;
+        self.next_tile_pattern_low.load_state(reader)?;
+        self.next_tile_pattern_high.load_state(reader)?;
+        self.pattern_shift_register_low.load_state(reader)?;
+        self.pattern_shift_register_high.load_state(reader)?;
+        self.attribute_shift_register_low.load_state(reader)?;
+        self.attribute_shift_register_high.load_state(reader)?;
+        self.scanline_sprites.load_state(reader)?;
+        self.scanline_sprite_count.load_state(reader)?;
+        self.is_sprite_zero_on_scanline.load_state(reader)?;
+        Ok(())
+    }
+}
+
+impl SaveState for Ppu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.save_state_without_frame(writer);
+        self.frame.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        self.load_state_without_frame(reader)?;
+        self.frame.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cartridge::Nrom, compiler::frontend::nes::InterpreterVisitor, test_utils};
+
+    fn run_until(
+        ppu: &mut Ppu,
+        cartridge: &impl crate::cartridge::Cartridge,
+        scanline: u16,
+        dot: u16,
+    ) {
+        while (ppu.scanline, ppu.dot) != (scanline, dot) {
+            ppu.step(cartridge);
+        }
+    }
+
+    /// Creates a cartridge whose tile 1 is solid and whose tile 2 only has
+    /// its leftmost column set, both using color 1.
+    fn cartridge_with_tiles() -> Nrom {
+        let mut chr = vec![0; 0x2000];
+        chr[0x10..0x18].fill(0xff);
+        chr[0x20..0x28].fill(0x80);
+        Nrom::new(&test_utils::prg_rom(&[], &[], &[]), &chr, false, None)
+    }
+
+    fn render_frames(ppu: &mut Ppu, cartridge: &impl crate::cartridge::Cartridge, count: u64) {
+        while ppu.frame_count < count {
+            ppu.step(cartridge);
+        }
+    }
+
+    #[test]
+    fn vblank_flag_is_set_during_vblank() {
+        let cartridge = test_utils::nrom(&[], &[], &[]);
+        let mut ppu = Ppu::new();
+
+        run_until(&mut ppu, &cartridge, 241, 1);
+        assert_eq!(ppu.status_register & 0b1000_0000, 0);
+        assert_eq!(ppu.frame_count, 0);
+        ppu.step(&cartridge);
+        assert_ne!(ppu.status_register & 0b1000_0000, 0);
+        assert_eq!(ppu.frame_count, 1);
+        assert!(!ppu.is_nmi_pending);
+
+        run_until(&mut ppu, &cartridge, 261, 1);
+        assert_ne!(ppu.status_register & 0b1000_0000, 0);
+        ppu.step(&cartridge);
+        assert_eq!(ppu.status_register & 0b1000_0000, 0);
+    }
+
+    #[test]
+    fn nmi_is_raised_at_vblank_start_if_enabled() {
+        let cartridge = test_utils::nrom(&[], &[], &[]);
+        let mut ppu = Ppu::new();
+        ppu.control_register = 0b1000_0000;
+
+        run_until(&mut ppu, &cartridge, 241, 1);
+        assert!(!ppu.is_nmi_pending);
+        ppu.step(&cartridge);
+        assert!(ppu.is_nmi_pending);
+    }
+
+    #[test]
+    fn odd_frames_are_one_dot_shorter_while_rendering() {
+        let cartridge = test_utils::nrom(&[], &[], &[]);
+        let mut frame_dot_counts = Vec::new();
+        for mask_register in [0b0000_0000, 0b0000_1000] {
+            let mut ppu = Ppu::new();
+            ppu.mask_register = mask_register;
+            for _ in 0..2 {
+                let dot_count = ppu.dot_count;
+                ppu.step(&cartridge);
+                run_until(&mut ppu, &cartridge, 0, 0);
+                frame_dot_counts.push(ppu.dot_count - dot_count);
+            }
+        }
+        assert_eq!(frame_dot_counts, [89342, 89342, 89342, 89341]);
+    }
+
+    #[test]
+    fn reading_status_at_vblank_start_suppresses_vblank_and_nmi() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.ppu.control_register = 0b1000_0000;
+        nes.ppu.scanline = 241;
+        nes.ppu.dot = 1;
+
+        let status = Ppu::read_register(&mut nes, &mut InterpreterVisitor::new(), 0x2002);
+        assert_eq!(status & 0b1000_0000, 0);
+        nes.ppu.step(&nes.cartridge);
+        assert_eq!(nes.ppu.status_register & 0b1000_0000, 0);
+        assert!(!nes.ppu.is_nmi_pending);
+        assert_eq!(nes.ppu.frame_count, 1);
+    }
+
+    #[test]
+    fn reading_status_right_after_vblank_start_suppresses_nmi() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.ppu.control_register = 0b1000_0000;
+        nes.ppu.scanline = 241;
+        nes.ppu.dot = 1;
+        nes.ppu.step(&nes.cartridge);
+        assert!(nes.ppu.is_nmi_pending);
+
+        let status = Ppu::read_register(&mut nes, &mut InterpreterVisitor::new(), 0x2002);
+        assert_ne!(status & 0b1000_0000, 0);
+        assert_eq!(nes.ppu.status_register & 0b1000_0000, 0);
+        assert!(!nes.ppu.is_nmi_pending);
+    }
+
+    #[test]
+    fn background_is_rendered() {
+        let cartridge = cartridge_with_tiles();
+        let mut ppu = Ppu::new();
+        ppu.mask_register = 0b0000_1010;
+        ppu.palette_ram[0x00] = 0x0f;
+        ppu.palette_ram[0x01] = 0x16;
+        ppu.palette_ram[0x05] = 0x21;
+        // tile 1 at the top left, using palette 1, and at (32, 0)
+        ppu.ram[0x000] = 1;
+        ppu.ram[0x004] = 1;
+        ppu.ram[0x3c0] = 0b01;
+
+        // the first frame starts without the tiles prefetched
+        render_frames(&mut ppu, &cartridge, 2);
+        assert_eq!(ppu.frame.pixel(0, 0), 0x21);
+        assert_eq!(ppu.frame.pixel(7, 7), 0x21);
+        assert_eq!(ppu.frame.pixel(8, 0), 0x0f);
+        assert_eq!(ppu.frame.pixel(0, 8), 0x0f);
+        assert_eq!(ppu.frame.pixel(32, 0), 0x16);
+    }
+
+    #[test]
+    fn background_is_hidden_in_left_column_if_clipped() {
+        let cartridge = cartridge_with_tiles();
+        let mut ppu = Ppu::new();
+        ppu.mask_register = 0b0000_1000;
+        ppu.palette_ram[0x00] = 0x0f;
+        ppu.palette_ram[0x01] = 0x16;
+        ppu.ram[..0x3c0].fill(1);
+
+        render_frames(&mut ppu, &cartridge, 2);
+        assert_eq!(ppu.frame.pixel(7, 0), 0x0f);
+        assert_eq!(ppu.frame.pixel(8, 0), 0x16);
+    }
+
+    #[test]
+    fn sprites_are_rendered() {
+        let cartridge = cartridge_with_tiles();
+        let mut ppu = Ppu::new();
+        ppu.mask_register = 0b0001_0100;
+        ppu.palette_ram[0x00] = 0x0f;
+        ppu.palette_ram[0x11] = 0x16;
+        ppu.palette_ram[0x15] = 0x21;
+        ppu.oam.fill(0xff);
+        // tile 2 on scanlines 10 to 17, then flipped horizontally using
+        // palette 1
+        ppu.oam[..8].copy_from_slice(&[9, 2, 0b0000_0000, 16, 9, 2, 0b0100_0001, 40]);
+
+        render_frames(&mut ppu, &cartridge, 1);
+        assert_eq!(ppu.frame.pixel(16, 9), 0x0f);
+        assert_eq!(ppu.frame.pixel(16, 10), 0x16);
+        assert_eq!(ppu.frame.pixel(16, 17), 0x16);
+        assert_eq!(ppu.frame.pixel(17, 10), 0x0f);
+        assert_eq!(ppu.frame.pixel(16, 18), 0x0f);
+        assert_eq!(ppu.frame.pixel(40, 10), 0x0f);
+        assert_eq!(ppu.frame.pixel(47, 10), 0x21);
+    }
+
+    #[test]
+    fn sprites_behind_background_are_hidden_by_opaque_pixels() {
+        let cartridge = cartridge_with_tiles();
+        let mut ppu = Ppu::new();
+        ppu.mask_register = 0b0001_1110;
+        ppu.palette_ram[0x01] = 0x16;
+        ppu.palette_ram[0x11] = 0x21;
+        ppu.oam.fill(0xff);
+        // solid sprites behind the background, over a solid tile at (16, 16)
+        // and an empty one at (24, 16)
+        ppu.oam[..8].copy_from_slice(&[15, 1, 0b0010_0000, 16, 15, 1, 0b0010_0000, 24]);
+        ppu.ram[0x042] = 1;
+
+        render_frames(&mut ppu, &cartridge, 2);
+        assert_eq!(ppu.frame.pixel(16, 16), 0x16);
+        assert_eq!(ppu.frame.pixel(24, 16), 0x21);
+    }
+
+    #[test]
+    fn sprite_zero_hit_is_set_over_opaque_background() {
+        let cartridge = cartridge_with_tiles();
+        for (tile_index, is_hit) in [(0, false), (1, true)] {
+            let mut ppu = Ppu::new();
+            ppu.mask_register = 0b0001_1110;
+            ppu.oam.fill(0xff);
+            ppu.oam[..4].copy_from_slice(&[15, 1, 0, 20]);
+            ppu.ram[0x042] = tile_index;
+
+            render_frames(&mut ppu, &cartridge, 2);
+            assert_eq!(ppu.status_register & 0b0100_0000 != 0, is_hit);
+            run_until(&mut ppu, &cartridge, 261, 2);
+            assert_eq!(ppu.status_register & 0b0100_0000, 0);
+        }
+    }
+
+    #[test]
+    fn sprite_zero_hit_is_not_set_by_other_sprites() {
+        let cartridge = cartridge_with_tiles();
+        let mut ppu = Ppu::new();
+        ppu.mask_register = 0b0001_1110;
+        ppu.oam.fill(0xff);
+        ppu.oam[4..8].copy_from_slice(&[15, 1, 0, 20]);
+        ppu.ram[0x042] = 1;
+
+        render_frames(&mut ppu, &cartridge, 2);
+        assert_eq!(ppu.status_register & 0b0100_0000, 0);
+    }
+
+    #[test]
+    fn sprite_overflow_is_set_by_more_than_eight_sprites_on_a_scanline() {
+        let cartridge = cartridge_with_tiles();
+        for (sprite_count, is_overflow) in [(8, false), (9, true)] {
+            let mut ppu = Ppu::new();
+            ppu.mask_register = 0b0001_1000;
+            ppu.oam.fill(0xff);
+            for sprite in ppu.oam.chunks_exact_mut(4).take(sprite_count) {
+                sprite[0] = 50;
+            }
+
+            render_frames(&mut ppu, &cartridge, 1);
+            assert_eq!(ppu.status_register & 0b0010_0000 != 0, is_overflow);
+            run_until(&mut ppu, &cartridge, 261, 2);
+            assert_eq!(ppu.status_register & 0b0010_0000, 0);
+        }
+    }
+
+    #[test]
+    fn sprite_palette_backdrop_entries_are_mirrored() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        for (address, mirror_address) in [
+            (0x3f10, 0x3f00),
+            (0x3f14, 0x3f04),
+            (0x3f18, 0x3f08),
+            (0x3f1c, 0x3f0c),
+            (0x3f00, 0x3f10),
+            (0x3f0c, 0x3f1c),
+            (0x3f20, 0x3f00),
+            (0x3ff1, 0x3f11),
+        ] {
+            let value = nes.ppu.palette_ram[usize::from(mirror_address & 0x1f)] + 1;
+            Ppu::write(&mut nes, &mut InterpreterVisitor::new(), address, value);
+            assert_eq!(
+                Ppu::read(&mut nes, &mut InterpreterVisitor::new(), mirror_address),
+                value,
+                "address 0x{address:04x}"
+            );
+        }
+
+        Ppu::write(&mut nes, &mut InterpreterVisitor::new(), 0x3f11, 0x30);
+        assert_ne!(
+            Ppu::read(&mut nes, &mut InterpreterVisitor::new(), 0x3f01),
+            0x30
+        );
+    }
+
+    #[test]
+    fn palette_reads_are_not_buffered() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.ppu.palette_ram[0x01] = 0x16;
+        nes.ppu.io_latch = 0b1010_1010;
+        // the nametable byte "underneath" the palette
+        nes.ppu.ram[0x701] = 0x55;
+
+        let value = Ppu::read(&mut nes, &mut InterpreterVisitor::new(), 0x3f01);
+        assert_eq!(value, 0b1001_0110);
+        assert_eq!(nes.ppu.read_buffer, 0x55);
+    }
+
+    #[test]
+    fn backdrop_is_shown_while_rendering_is_disabled() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        Ppu::write(&mut nes, &mut InterpreterVisitor::new(), 0x3f10, 0x2a);
+
+        render_frames(&mut nes.ppu, &nes.cartridge, 1);
+        assert!(nes.ppu.frame.pixels().iter().all(|pixel| *pixel == 0x2a));
+    }
+
+    /// A program which writes $5a to the given PPU address through PPUDATA,
+    /// then reads it back twice, storing the values read at $00 and $01.
+    fn ppudata_program(address: u16) -> Vec<u8> {
+        let [address_low, address_high] = address.to_le_bytes();
+        [
+            &[0x78][..],                             // SEI
+            &[0xa9, address_high, 0x8d, 0x06, 0x20], // LDA #high; STA $2006
+            &[0xa9, address_low, 0x8d, 0x06, 0x20],  // LDA #low; STA $2006
+            &[0xa9, 0x5a, 0x8d, 0x07, 0x20],         // LDA #$5a; STA $2007
+            &[0xa9, address_high, 0x8d, 0x06, 0x20], // LDA #high; STA $2006
+            &[0xa9, address_low, 0x8d, 0x06, 0x20],  // LDA #low; STA $2006
+            &[0xad, 0x07, 0x20, 0x85, 0x00],         // LDA $2007; STA $00
+            &[0xad, 0x07, 0x20, 0x85, 0x01],         // LDA $2007; STA $01
+            &[0x4c, 0x24, 0x80],                     // JMP $8024
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn ppudata_reads_are_buffered() {
+        for address in [0x0010, 0x2100, 0x3e00] {
+            for is_jit_enabled in [false, true] {
+                let mut nopt = test_utils::nopt(&ppudata_program(address));
+                nopt.set_jit_enabled(is_jit_enabled);
+                nopt.nes_mut().ppu.read_buffer = 0x77;
+                while nopt.nes().cpu.pc != 0x8024 {
+                    nopt.run();
+                }
+
+                let ram = &nopt.nes().cpu.ram;
+                assert_eq!(ram[..2], [0x77, 0x5a], "address 0x{address:04x}");
+            }
+        }
+    }
+
+    #[test]
+    fn ppudata_accesses_increment_address() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.ppu.current_address = 0x2000;
+        Ppu::read_register(&mut nes, &mut InterpreterVisitor::new(), 0x2007);
+        assert_eq!(nes.ppu.current_address, 0x2001);
+
+        nes.ppu.control_register = 0b0000_0100;
+        Ppu::write_register(&mut nes, &mut InterpreterVisitor::new(), 0x2007, 0);
+        assert_eq!(nes.ppu.current_address, 0x2021);
+    }
+
+    fn read_register(nes: &mut Nes<Nrom>, address: u16) -> u8 {
+        Ppu::read_register(nes, &mut InterpreterVisitor::new(), address)
+    }
+
+    fn write_register(nes: &mut Nes<Nrom>, address: u16, value: u8) {
+        Ppu::write_register(nes, &mut InterpreterVisitor::new(), address, value);
+    }
+
+    #[test]
+    fn reading_status_clears_vblank_and_write_toggle() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.ppu.status_register = 0b1110_0000;
+        nes.ppu.io_latch = 0b0001_0101;
+        nes.ppu.write_toggle = true;
+
+        assert_eq!(read_register(&mut nes, 0x2002), 0b1111_0101);
+        assert_eq!(nes.ppu.status_register, 0b0110_0000);
+        assert!(!nes.ppu.write_toggle);
+        assert_eq!(read_register(&mut nes, 0x2002), 0b0111_0101);
+    }
+
+    #[test]
+    fn oamdata_writes_increment_oam_address() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        write_register(&mut nes, 0x2003, 0xfe);
+        for value in [0x11, 0x22, 0x33] {
+            write_register(&mut nes, 0x2004, value);
+        }
+        assert_eq!(nes.ppu.oam[0xfe..], [0x11, 0x22]);
+        assert_eq!(nes.ppu.oam[0x00], 0x33);
+        assert_eq!(nes.ppu.oam_address, 0x01);
+    }
+
+    #[test]
+    fn oamdata_reads_do_not_increment_oam_address() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.ppu.oam[0x40] = 0x44;
+        write_register(&mut nes, 0x2003, 0x40);
+        assert_eq!(read_register(&mut nes, 0x2004), 0x44);
+        assert_eq!(read_register(&mut nes, 0x2004), 0x44);
+        assert_eq!(nes.ppu.oam_address, 0x40);
+    }
+
+    #[test]
+    fn registers_are_mirrored_every_eight_bytes() {
+        for base_address in [0x2008, 0x2ff0, 0x3ff8] {
+            let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+            nes.ppu.status_register = 0b1000_0000;
+            write_register(&mut nes, base_address + 3, 0x20);
+            write_register(&mut nes, base_address + 4, 0x55);
+            assert_eq!(nes.ppu.oam[0x20], 0x55);
+            write_register(&mut nes, base_address + 3, 0x20);
+            assert_eq!(read_register(&mut nes, base_address + 4), 0x55);
+
+            write_register(&mut nes, base_address, 0b0000_0100);
+            assert_eq!(nes.ppu.control_register, 0b0000_0100);
+            write_register(&mut nes, base_address + 1, 0b0000_0001);
+            assert_eq!(nes.ppu.mask_register, 0b0000_0001);
+            assert_ne!(read_register(&mut nes, base_address + 2) & 0b1000_0000, 0);
+            assert_eq!(nes.ppu.status_register, 0);
+
+            write_register(&mut nes, base_address + 6, 0x21);
+            write_register(&mut nes, base_address + 6, 0x08);
+            assert_eq!(nes.ppu.current_address, 0x2108);
+            write_register(&mut nes, base_address + 7, 0x66);
+            assert_eq!(nes.ppu.ram[0x108], 0x66);
+            assert_eq!(nes.ppu.current_address, 0x2128);
+        }
+    }
+
+    /// The loopy registers as (t, v, x, w).
+    fn loopy_registers(nes: &Nes<Nrom>) -> (u16, u16, u8, bool) {
+        (
+            nes.ppu.temporary_address,
+            nes.ppu.current_address,
+            nes.ppu.fine_x_scroll,
+            nes.ppu.write_toggle,
+        )
+    }
+
+    #[test]
+    fn register_writes_update_loopy_registers() {
+        // the example sequence of the nesdev wiki's PPU scrolling page
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.ppu.temporary_address = 0x7fff;
+        nes.ppu.current_address = 0x1234;
+        nes.ppu.write_toggle = true;
+
+        write_register(&mut nes, 0x2000, 0x00);
+        assert_eq!(loopy_registers(&nes), (0x73ff, 0x1234, 0, true));
+        read_register(&mut nes, 0x2002);
+        assert_eq!(loopy_registers(&nes), (0x73ff, 0x1234, 0, false));
+        write_register(&mut nes, 0x2005, 0x7d);
+        assert_eq!(loopy_registers(&nes), (0x73ef, 0x1234, 5, true));
+        write_register(&mut nes, 0x2005, 0x5e);
+        assert_eq!(loopy_registers(&nes), (0x616f, 0x1234, 5, false));
+        write_register(&mut nes, 0x2006, 0x3d);
+        assert_eq!(loopy_registers(&nes), (0x3d6f, 0x1234, 5, true));
+        write_register(&mut nes, 0x2006, 0xf0);
+        assert_eq!(loopy_registers(&nes), (0x3df0, 0x3df0, 5, false));
+
+        write_register(&mut nes, 0x2000, 0x01);
+        assert_eq!(loopy_registers(&nes), (0x35f0, 0x3df0, 5, false));
+    }
+
+    #[test]
+    fn first_ppuaddr_write_clears_bit_14() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        nes.ppu.temporary_address = 0x4000;
+        write_register(&mut nes, 0x2006, 0xff);
+        assert_eq!(loopy_registers(&nes), (0x3f00, 0, 0, true));
+        write_register(&mut nes, 0x2006, 0x12);
+        assert_eq!(loopy_registers(&nes), (0x3f12, 0x3f12, 0, false));
+    }
+
+    #[test]
+    fn status_read_restarts_write_pairs() {
+        let mut nes = Nes::new(test_utils::nrom(&[], &[], &[]));
+        write_register(&mut nes, 0x2005, 0x7d);
+        read_register(&mut nes, 0x2002);
+        write_register(&mut nes, 0x2005, 0x5e);
+        assert_eq!(loopy_registers(&nes), (0x000b, 0, 6, true));
+
+        read_register(&mut nes, 0x2002);
+        write_register(&mut nes, 0x2006, 0x3d);
+        read_register(&mut nes, 0x2002);
+        write_register(&mut nes, 0x2006, 0x21);
+        assert_eq!(loopy_registers(&nes), (0x210b, 0, 6, true));
+        write_register(&mut nes, 0x2006, 0x08);
+        assert_eq!(loopy_registers(&nes), (0x2108, 0x2108, 6, false));
+    }
+}
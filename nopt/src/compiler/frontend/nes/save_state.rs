@@ -0,0 +1,211 @@
+use std::fmt;
+
+/// Identifies a save state produced by this crate.
+const MAGIC: [u8; 4] = *b"NOPT";
+
+/// The version of the save state format, to be incremented whenever the
+/// layout of the saved state changes.
+pub const VERSION: u32 = 2;
+
+/// A component whose state can be written to and restored from a save state.
+///
+/// Implementations must read back exactly the bytes that they write.
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+
+    /// # Errors
+    ///
+    /// Returns an error if the saved state is malformed.
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadStateError {
+    /// The data does not start with the save state header.
+    InvalidHeader,
+    /// The save state was produced by an incompatible version.
+    UnsupportedVersion(u32),
+    /// The size of the save state does not match the machine it is being
+    /// loaded into, e.g. because it was saved with a different cartridge.
+    SizeMismatch,
+}
+
+impl fmt::Display for LoadStateError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(formatter, "not a save state"),
+            Self::UnsupportedVersion(version) => {
+                write!(formatter, "unsupported save state version {version}")
+            }
+            Self::SizeMismatch => write!(formatter, "save state does not match the machine"),
+        }
+    }
+}
+
+impl std::error::Error for LoadStateError {}
+
+pub struct StateWriter {
+    /// The bytes written so far, unless they are only being counted.
+    bytes: Option<Vec<u8>>,
+    len: usize,
+}
+
+impl StateWriter {
+    pub(super) fn new() -> Self {
+        Self::with_bytes(Some(Vec::new()))
+    }
+
+    /// Creates a writer which only counts the bytes written to it, to measure
+    /// the size of a state without producing it.
+    pub(super) fn counting() -> Self {
+        Self::with_bytes(None)
+    }
+
+    fn with_bytes(bytes: Option<Vec<u8>>) -> Self {
+        let mut writer = Self { bytes, len: 0 };
+        writer.write_bytes(&MAGIC);
+        VERSION.save_state(&mut writer);
+        writer
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.len += bytes.len();
+        if let Some(written_bytes) = &mut self.bytes {
+            written_bytes.extend_from_slice(bytes);
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(super) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+            .expect("the writer should not be only counting bytes")
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Validates the header of a save state, returning a reader positioned
+    /// right after it.
+    pub(super) fn new(bytes: &'a [u8]) -> Result<Self, LoadStateError> {
+        let mut reader = Self { bytes };
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(LoadStateError::InvalidHeader);
+        }
+        let mut version = 0u32;
+        version.load_state(&mut reader)?;
+        if version != VERSION {
+            return Err(LoadStateError::UnsupportedVersion(version));
+        }
+        Ok(reader)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if fewer than `length` bytes are left.
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], LoadStateError> {
+        if self.bytes.len() < length {
+            return Err(LoadStateError::SizeMismatch);
+        }
+        let (bytes, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl SaveState for u8 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[*self]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        *self = reader.read_bytes(1)?[0];
+        Ok(())
+    }
+}
+
+impl SaveState for u16 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.to_le_bytes());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        *self = Self::from_le_bytes(reader.read_bytes(size_of::<Self>())?.try_into().unwrap());
+        Ok(())
+    }
+}
+
+impl SaveState for u32 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.to_le_bytes());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        *self = Self::from_le_bytes(reader.read_bytes(size_of::<Self>())?.try_into().unwrap());
+        Ok(())
+    }
+}
+
+impl SaveState for u64 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.to_le_bytes());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        *self = Self::from_le_bytes(reader.read_bytes(size_of::<Self>())?.try_into().unwrap());
+        Ok(())
+    }
+}
+
+impl SaveState for bool {
+    fn save_state(&self, writer: &mut StateWriter) {
+        u8::from(*self).save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        *self = reader.read_bytes(1)?[0] != 0;
+        Ok(())
+    }
+}
+
+impl SaveState for Option<u8> {
+    // always takes up two bytes, so that the size of a save state does not
+    // depend on the state itself
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.is_some().save_state(writer);
+        self.unwrap_or(0).save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        let mut is_some = false;
+        let mut value = 0;
+        is_some.load_state(reader)?;
+        value.load_state(reader)?;
+        *self = is_some.then_some(value);
+        Ok(())
+    }
+}
+
+impl<T: SaveState, const N: usize> SaveState for [T; N] {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for element in self {
+            element.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader<'_>) -> Result<(), LoadStateError> {
+        for element in self {
+            element.load_state(reader)?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::RangeInclusive;
+
 pub trait Visitor: Sized {
     type U1: Copy;
     type U8: Copy;
@@ -16,6 +18,10 @@ pub trait Visitor: Sized {
         self.concatenate(high, low)
     }
 
+    /// The value of a variable, if it is known without running the emitted
+    /// code.
+    fn known_u16(&self, value: Self::U16) -> Option<u16>;
+
     fn memory_u8(&mut self, address: *const u8) -> Self::U8 {
         let n0 = self.immediate_u16(0);
         self.memory_with_offset_u8(address, n0)
@@ -29,6 +35,12 @@ pub trait Visitor: Sized {
         self.concatenate(high, low)
     }
 
+    fn memory_bool(&mut self, address: *const bool) -> Self::U1 {
+        const { assert!(size_of::<bool>() == size_of::<u8>()) };
+        let byte = self.memory_u8(address.cast());
+        self.get_bit(byte, 0)
+    }
+
     fn set_memory_u8(&mut self, address: *mut u8, value: Self::U8) {
         let n0 = self.immediate_u16(0);
         self.set_memory_with_offset_u8(address, n0, value);
@@ -45,12 +57,35 @@ pub trait Visitor: Sized {
 
     fn set_memory_with_offset_u8(&mut self, address: *mut u8, offset: Self::U16, value: Self::U8);
 
+    fn set_memory_bool(&mut self, address: *mut bool, value: Self::U1) {
+        const { assert!(size_of::<bool>() == size_of::<u8>()) };
+        let byte = self.if_else_with_result(
+            value,
+            |mut visitor| {
+                let n1 = visitor.immediate_u8(1);
+                visitor.terminate(Some(n1));
+            },
+            |mut visitor| {
+                let n0 = visitor.immediate_u8(0);
+                visitor.terminate(Some(n0));
+            },
+        );
+        self.set_memory_u8(address.cast(), byte);
+    }
+
+    fn call_native(&mut self, function: unsafe extern "C" fn(*mut u8), argument: *mut u8);
+
     fn get_bit(&mut self, value: Self::U8, bit_index: u8) -> Self::U1;
 
     fn not(&mut self, operand: Self::U1) -> Self::U1;
 
     fn is_zero(&mut self, operand: Self::U8) -> Self::U1;
 
+    fn shift_left(&mut self, operand: Self::U8) -> Self::U8 {
+        let r#false = self.immediate_u1(false);
+        self.rotate_left(operand, r#false)
+    }
+
     fn shift_right(&mut self, operand: Self::U8) -> Self::U8 {
         let r#false = self.immediate_u1(false);
         self.rotate_right(operand, r#false)
@@ -66,6 +101,18 @@ pub trait Visitor: Sized {
 
     fn less_than_or_equal(&mut self, operand_0: Self::U16, operand_1: Self::U16) -> Self::U1;
 
+    fn is_in_range(&mut self, operand: Self::U16, range: RangeInclusive<u16>) -> Self::U1 {
+        let lower_bound_condition = {
+            let start = self.immediate_u16(*range.start());
+            self.less_than_or_equal(start, operand)
+        };
+        let upper_bound_condition = {
+            let end = self.immediate_u16(*range.end());
+            self.less_than_or_equal(operand, end)
+        };
+        self.and_u1(lower_bound_condition, upper_bound_condition)
+    }
+
     fn select(
         &mut self,
         condition: Self::U1,
@@ -0,0 +1,189 @@
+use crate::compiler::frontend::nes::Nes;
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Stops running once the CPU accesses any address in a range. Reads made by
+/// DMA transfers are not accesses of the CPU.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addresses: RangeInclusive<u16>,
+    pub access: Access,
+    /// The value which must be read or written for the watchpoint to
+    /// trigger, if any.
+    pub value: Option<u8>,
+}
+
+impl Watchpoint {
+    /// Emits code recording the first access which triggers a watchpoint.
+    /// Watchpoints which cannot be triggered by the access, as its address is
+    /// known at compile time, are skipped. The watchpoints are baked into the
+    /// emitted code, which must be discarded once they change.
+    pub(super) fn visit_access<Cartridge: crate::cartridge::Cartridge, Visitor: super::Visitor>(
+        nes: &mut Nes<Cartridge>,
+        visitor: &mut Visitor,
+        access: Access,
+        address: Visitor::U16,
+        value: Visitor::U8,
+    ) {
+        let known_address = visitor.known_u16(address);
+        for watchpoint in &nes.watchpoints {
+            if !matches!(
+                (watchpoint.access, access),
+                (Access::ReadWrite, _)
+                    | (Access::Read, Access::Read)
+                    | (Access::Write, Access::Write)
+            ) || known_address.is_some_and(|address| !watchpoint.addresses.contains(&address))
+            {
+                continue;
+            }
+
+            let is_watchpoint_hit = visitor.memory_bool(&raw const nes.is_watchpoint_hit);
+            let mut condition = visitor.not(is_watchpoint_hit);
+            if known_address.is_none() {
+                let is_address_watched = visitor.is_in_range(address, watchpoint.addresses.clone());
+                condition = visitor.and_u1(condition, is_address_watched);
+            }
+            if let Some(expected_value) = watchpoint.value {
+                let expected_value = visitor.immediate_u8(expected_value);
+                let difference = visitor.xor(value, expected_value);
+                let is_value_watched = visitor.is_zero(difference);
+                condition = visitor.and_u1(condition, is_value_watched);
+            }
+
+            let is_watchpoint_hit = &raw mut nes.is_watchpoint_hit;
+            let watchpoint_address = &raw mut nes.watchpoint_address;
+            let watchpoint_value = &raw mut nes.watchpoint_value;
+            visitor.r#if(condition, |mut visitor| {
+                let r#true = visitor.immediate_u1(true);
+                visitor.set_memory_bool(is_watchpoint_hit, r#true);
+                visitor.set_memory_u16(watchpoint_address, address);
+                visitor.set_memory_u8(watchpoint_value, value);
+                visitor.terminate(None);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Nopt, Stopped, cartridge::Nrom, test_utils};
+
+    // SEI; loop: LDA $10; STA $11; INC $12; JMP loop
+    const PROGRAM: [u8; 10] = [0x78, 0xa5, 0x10, 0x85, 0x11, 0xe6, 0x12, 0x4c, 0x01, 0x80];
+
+    fn watchpoint(addresses: RangeInclusive<u16>, access: Access, value: Option<u8>) -> Watchpoint {
+        Watchpoint {
+            addresses,
+            access,
+            value,
+        }
+    }
+
+    fn stopped(address: u16, value: u8, pc: u16) -> Stopped {
+        Stopped::Watchpoint { address, value, pc }
+    }
+
+    /// Returns the first stops while running a bounded number of times, with
+    /// and without compilation.
+    fn stops(program: &[u8], watchpoints: &[Watchpoint], count: usize) -> [Vec<Stopped>; 2] {
+        [false, true].map(|is_jit_enabled| {
+            let mut nopt = test_utils::nopt(program);
+            nopt.set_jit_enabled(is_jit_enabled);
+            nopt.set_watchpoints(watchpoints.to_vec());
+            run_for_stops(&mut nopt, count)
+        })
+    }
+
+    fn run_for_stops(nopt: &mut Nopt<Nrom>, count: usize) -> Vec<Stopped> {
+        (0..10_000).filter_map(|_| nopt.run()).take(count).collect()
+    }
+
+    #[test]
+    fn reads_trigger_read_watchpoints() {
+        let watchpoints = [watchpoint(0x0010..=0x0012, Access::Read, None)];
+        let expected_stops = vec![
+            stopped(0x0010, 0, 0x8001),
+            stopped(0x0012, 0, 0x8005),
+            stopped(0x0010, 0, 0x8001),
+        ];
+        assert_eq!(
+            stops(&PROGRAM, &watchpoints, 3),
+            [0, 1].map(|_| expected_stops.clone())
+        );
+    }
+
+    #[test]
+    fn writes_trigger_write_watchpoints() {
+        let watchpoints = [watchpoint(0x0011..=0x0011, Access::Write, None)];
+        let expected_stops = vec![stopped(0x0011, 0, 0x8003), stopped(0x0011, 0, 0x8003)];
+        assert_eq!(
+            stops(&PROGRAM, &watchpoints, 2),
+            [0, 1].map(|_| expected_stops.clone())
+        );
+
+        let watchpoints = [watchpoint(0x0000..=0x00ff, Access::Write, Some(2))];
+        let expected_stops = vec![stopped(0x0012, 2, 0x8005)];
+        assert_eq!(
+            stops(&PROGRAM, &watchpoints, 1),
+            [0, 1].map(|_| expected_stops.clone())
+        );
+    }
+
+    #[test]
+    fn reads_and_writes_trigger_read_write_watchpoints() {
+        let watchpoints = [watchpoint(0x0010..=0x0011, Access::ReadWrite, None)];
+        let expected_stops = vec![
+            stopped(0x0010, 0, 0x8001),
+            stopped(0x0011, 0, 0x8003),
+            stopped(0x0010, 0, 0x8001),
+        ];
+        assert_eq!(
+            stops(&PROGRAM, &watchpoints, 3),
+            [0, 1].map(|_| expected_stops.clone())
+        );
+    }
+
+    #[test]
+    fn values_must_match() {
+        let watchpoints = [watchpoint(0x0010..=0x0010, Access::Read, Some(5))];
+        for is_jit_enabled in [false, true] {
+            let mut nopt = test_utils::nopt(&PROGRAM);
+            nopt.set_jit_enabled(is_jit_enabled);
+            nopt.set_watchpoints(watchpoints.to_vec());
+            assert_eq!(run_for_stops(&mut nopt, 1), []);
+            nopt.nes_mut().cpu.ram[0x10] = 5;
+            assert_eq!(run_for_stops(&mut nopt, 1), [stopped(0x0010, 5, 0x8001)]);
+        }
+    }
+
+    #[test]
+    fn dma_reads_do_not_trigger_watchpoints() {
+        // SEI; play a DMC sample from $c000; wait until it has been read;
+        // start an OAM DMA from $0200; LDA $0205; loop: JMP loop
+        let program = [
+            &[0x78][..],
+            &[0xa9, 0x00, 0x8d, 0x12, 0x40, 0x8d, 0x13, 0x40],
+            &[0xa9, 0x0f, 0x8d, 0x10, 0x40, 0xa9, 0x10, 0x8d, 0x15, 0x40],
+            &[0xad, 0x15, 0x40, 0x29, 0x10, 0xd0, 0xf9],
+            &[0xa9, 0x02, 0x8d, 0x14, 0x40],
+            &[0xad, 0x05, 0x02, 0x4c, 0x22, 0x80],
+        ]
+        .concat();
+        let watchpoints = [
+            watchpoint(0x0200..=0x02ff, Access::Read, None),
+            watchpoint(0xc000..=0xc0ff, Access::Read, None),
+        ];
+        let expected_stops = vec![stopped(0x0205, 0, 0x801f)];
+        assert_eq!(
+            stops(&program, &watchpoints, 2),
+            [0, 1].map(|_| expected_stops.clone())
+        );
+    }
+}
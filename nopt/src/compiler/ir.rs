@@ -81,6 +81,11 @@ pub(super) enum Instruction {
         destination: Destination8,
         variable: Variable8,
     },
+    /// Calls a host function with a pointer into the machine state.
+    CallNative {
+        function: unsafe extern "C" fn(*mut u8),
+        argument_state_offset: usize,
+    },
 }
 
 impl Debug for Instruction {
@@ -102,6 +107,12 @@ impl Debug for Instruction {
                 destination,
                 variable,
             } => write!(f, "{destination:?} = {variable:?}"),
+            Self::CallNative {
+                function,
+                argument_state_offset,
+            } => {
+                write!(f, "call {function:?}(state + 0x{argument_state_offset:x})")
+            }
         }
     }
 }
@@ -240,8 +251,9 @@ impl Debug for Variable8 {
 #[derive(Clone)]
 pub(crate) enum Definition8 {
     BasicBlockArgument,
+    /// A byte located at `state_offset` + `offset` within the machine state.
     NativeMemory {
-        address: *const u8,
+        state_offset: usize,
         offset: Variable16,
     },
     Immediate(u8),
@@ -274,8 +286,11 @@ impl Debug for Definition8 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::BasicBlockArgument => write!(f, "arg"),
-            Self::NativeMemory { address, offset } => {
-                write!(f, "{address:?}[{offset:?}]")
+            Self::NativeMemory {
+                state_offset,
+                offset,
+            } => {
+                write!(f, "state[0x{state_offset:x} + {offset:?}]")
             }
             Self::Immediate(immediate) => write!(f, "0x{immediate:02x}"),
             Self::LowByte(variable) => write!(f, "<{variable:?}"),
@@ -307,8 +322,9 @@ impl Debug for Definition8 {
 
 #[derive(Clone)]
 pub(super) enum Destination8 {
+    /// A byte located at `state_offset` + `offset` within the machine state.
     NativeMemory {
-        address: *mut u8,
+        state_offset: usize,
         offset: Variable16,
     },
 }
@@ -316,7 +332,10 @@ pub(super) enum Destination8 {
 impl Debug for Destination8 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::NativeMemory { address, offset } => write!(f, "{address:?}[{offset:?}"),
+            Self::NativeMemory {
+                state_offset,
+                offset,
+            } => write!(f, "state[0x{state_offset:x} + {offset:?}]"),
         }
     }
 }
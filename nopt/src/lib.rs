@@ -1,14 +1,54 @@
 mod compiler;
+pub mod movie;
 mod nes_assembly;
+pub mod ram_search;
+pub mod rewind;
+pub mod state_hash;
+#[cfg(test)]
+mod test_utils;
 
-use crate::compiler::{Compiler, frontend::nes::Nes};
-pub use compiler::frontend::nes::cartridge;
-use std::mem::ManuallyDrop;
+use crate::compiler::{Compiler, frontend, frontend::nes::Nes};
+use cheat::Cheat;
+pub use compiler::frontend::nes::{
+    Buttons, Frame, cartridge, cheat, input_device, save_state, watchpoint,
+};
+use state_hash::StateHashLog;
+use std::{cell::RefCell, collections::BTreeSet, mem::ManuallyDrop, rc::Rc};
 use tracing::trace;
+use watchpoint::Watchpoint;
+
+/// Why running was interrupted before the requested amount of emulation was
+/// completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stopped {
+    /// The CPU is about to run the instruction at the given address, on which
+    /// a breakpoint is set.
+    Breakpoint(u16),
+    /// The instruction at `pc` triggered a watchpoint by reading or writing
+    /// `value` at `address`. Any further accesses it made were completed.
+    Watchpoint { address: u16, value: u8, pc: u16 },
+}
+
+#[derive(Clone, Copy)]
+struct CompiledFunction {
+    function: unsafe extern "C" fn(*mut u8),
+    /// How running the function changes [`Nopt::call_depth`].
+    call_depth_change: i64,
+}
 
 pub struct Nopt<Cartridge: cartridge::Cartridge> {
     nes: Nes<Cartridge>,
-    prg_rom_functions: Vec<Option<unsafe extern "C" fn()>>,
+    /// Compiled code for each PRG ROM address, shared between forks.
+    prg_rom_functions: Rc<RefCell<Vec<Option<CompiledFunction>>>>,
+    is_jit_enabled: bool,
+    state_hash_log: Option<StateHashLog>,
+    breakpoints: BTreeSet<u16>,
+    /// The address of the breakpoint last stopped at, as long as the CPU is
+    /// still there, so that running resumes past it.
+    stopped_breakpoint: Option<u16>,
+    /// The number of subroutines and interrupt handlers entered but not yet
+    /// returned from, relative to power-on.
+    call_depth: i64,
 }
 
 impl<Cartridge: cartridge::Cartridge> Nopt<Cartridge> {
@@ -18,7 +58,30 @@ impl<Cartridge: cartridge::Cartridge> Nopt<Cartridge> {
         let functions = vec![None; 0x8000];
         Self {
             nes,
-            prg_rom_functions: functions,
+            prg_rom_functions: Rc::new(RefCell::new(functions)),
+            is_jit_enabled: true,
+            state_hash_log: None,
+            breakpoints: BTreeSet::new(),
+            stopped_breakpoint: None,
+            call_depth: 0,
+        }
+    }
+
+    /// Creates an independent copy of the machine, which shares compiled code
+    /// with this one.
+    #[must_use]
+    pub fn fork(&self) -> Self
+    where
+        Cartridge: Clone,
+    {
+        Self {
+            nes: self.nes.clone(),
+            prg_rom_functions: Rc::clone(&self.prg_rom_functions),
+            is_jit_enabled: self.is_jit_enabled,
+            state_hash_log: self.state_hash_log.clone(),
+            breakpoints: self.breakpoints.clone(),
+            stopped_breakpoint: self.stopped_breakpoint,
+            call_depth: self.call_depth,
         }
     }
 
@@ -31,33 +94,218 @@ impl<Cartridge: cartridge::Cartridge> Nopt<Cartridge> {
         &mut self.nes
     }
 
-    /// # Safety
+    /// Chooses between running compiled code (the default) and interpreting
+    /// each instruction, which is slower but serves as a reference.
+    pub fn set_jit_enabled(&mut self, is_jit_enabled: bool) {
+        self.is_jit_enabled = is_jit_enabled;
+    }
+
+    /// Starts recording hashes of the machine state into the given log, or
+    /// stops if `None` is given.
+    pub fn set_state_hash_log(&mut self, state_hash_log: Option<StateHashLog>) {
+        self.state_hash_log = state_hash_log;
+    }
+
+    #[must_use]
+    pub fn state_hash_log(&self) -> Option<&StateHashLog> {
+        self.state_hash_log.as_ref()
+    }
+
+    #[must_use]
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.nes.cheats
+    }
+
+    /// Replaces the active cheats. Compiled code is discarded, and this
+    /// machine stops sharing code with its forks, as cheats are baked into
+    /// the code.
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.nes.cheats = cheats;
+        self.discard_compiled_code();
+    }
+
+    #[must_use]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.nes.watchpoints
+    }
+
+    /// Replaces the active watchpoints. Like [`Nopt::set_cheats`], this
+    /// discards compiled code and stops sharing code with forks.
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.nes.watchpoints = watchpoints;
+        self.discard_compiled_code();
+    }
+
+    fn discard_compiled_code(&mut self) {
+        self.prg_rom_functions = Rc::new(RefCell::new(vec![None; 0x8000]));
+    }
+
+    #[must_use]
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    /// Sets a breakpoint at the given CPU address, making running stop right
+    /// before the instruction there is run.
     ///
-    /// The safety of this function is conditional on the safety of the
-    /// underlying backend.
-    pub unsafe fn run(&mut self) {
+    /// Breakpoints are only checked between functions of compiled code. Like
+    /// [`Nopt::set_cheats`], this discards compiled code, which may run past
+    /// the address, and stops sharing code with forks.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if self.breakpoints.insert(address) {
+            self.discard_compiled_code();
+        }
+    }
+
+    /// Returns whether a breakpoint was set at the given address.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        self.nes.save_state()
+    }
+
+    /// Restores a state produced by [`Nopt::save_state`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state cannot be loaded, see
+    /// [`Nes::load_state`].
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), save_state::LoadStateError> {
+        // compiled code only depends on PRG ROM, cheats and watchpoints, none
+        // of which are part of the state, so it remains valid
+        self.nes.load_state(state)?;
+        self.stopped_breakpoint = None;
+        Ok(())
+    }
+
+    /// Runs until the PPU has completed rendering the current frame, or until
+    /// stopped early. Running another frame afterwards completes the frame.
+    pub fn run_frame(&mut self) -> Option<Stopped> {
+        let frame_count = self.nes.ppu.frame_count;
+        while self.nes.ppu.frame_count == frame_count {
+            if let Some(stopped) = self.run() {
+                return Some(stopped);
+            }
+        }
+        None
+    }
+
+    /// Runs a frame with the given states of the standard controllers plugged
+    /// into both ports.
+    pub(crate) fn run_frame_with_buttons(&mut self, buttons: [Buttons; 2]) {
+        for (port, buttons) in buttons.into_iter().enumerate() {
+            self.nes.set_controller_state(port, buttons);
+        }
+        // recordings must stay in sync with frames, so stops are ignored
+        while self.run_frame().is_some() {}
+    }
+
+    /// The number of subroutines and interrupt handlers entered but not yet
+    /// returned from. This may drift, or even become negative, if the program
+    /// manipulates the stack to jump rather than to call or return.
+    #[must_use]
+    pub fn call_depth(&self) -> i64 {
+        self.call_depth
+    }
+
+    /// Runs the next function of compiled code, currently a single
+    /// instruction, along with any interrupt it triggers.
+    pub fn run(&mut self) -> Option<Stopped> {
+        self.run_instruction(self.is_jit_enabled)
+    }
+
+    /// Runs exactly one instruction, along with any interrupt it triggers,
+    /// by interpreting it.
+    pub fn step_instruction(&mut self) -> Option<Stopped> {
+        self.run_instruction(false)
+    }
+
+    /// Runs one instruction, then continues until any subroutine or
+    /// interrupt handler it entered has returned, unless stopped early.
+    pub fn step_over(&mut self) -> Option<Stopped> {
+        let call_depth = self.call_depth;
+        let mut stopped = self.step_instruction();
+        while stopped.is_none() && self.call_depth > call_depth {
+            stopped = self.run();
+        }
+        stopped
+    }
+
+    /// Runs until the current subroutine or interrupt handler has returned,
+    /// unless stopped early. This never finishes if it does not return.
+    pub fn step_out(&mut self) -> Option<Stopped> {
+        let call_depth = self.call_depth;
+        loop {
+            let stopped = self.run();
+            if stopped.is_some() || self.call_depth < call_depth {
+                return stopped;
+            }
+        }
+    }
+
+    fn run_instruction(&mut self, is_compiled: bool) -> Option<Stopped> {
+        let pc = self.nes.cpu.pc;
+        if self.stopped_breakpoint.take() != Some(pc) && self.breakpoints.contains(&pc) {
+            self.stopped_breakpoint = Some(pc);
+            return Some(Stopped::Breakpoint(pc));
+        }
+
+        let call_depth_change = if is_compiled {
+            self.run_compiled()
+        } else {
+            trace!("interpreting with pc: 0x{pc:04x}");
+            frontend::interpret_instruction(&mut self.nes, pc).call_depth_change()
+        };
+        let is_interrupted = self.finish_instruction();
+        self.call_depth += call_depth_change + i64::from(is_interrupted);
+
+        if self.nes.is_watchpoint_hit {
+            self.nes.is_watchpoint_hit = false;
+            return Some(Stopped::Watchpoint {
+                address: self.nes.watchpoint_address,
+                value: self.nes.watchpoint_value,
+                pc,
+            });
+        }
+
+        let next_pc = self.nes.cpu.pc;
+        self.breakpoints.contains(&next_pc).then(|| {
+            self.stopped_breakpoint = Some(next_pc);
+            Stopped::Breakpoint(next_pc)
+        })
+    }
+
+    /// Returns how the call depth changed.
+    fn run_compiled(&mut self) -> i64 {
         let pc = self.nes().cpu.pc;
 
         let mut compile = || {
             trace!("compiling function at 0x{pc:04x}");
 
-            let (mmap, is_prg_rom_only) = Compiler::new(true).compile(&mut self.nes);
+            let (mmap, mnemonic, is_prg_rom_only) = Compiler::new(true).compile(&mut self.nes);
 
             (
-                unsafe {
-                    std::mem::transmute::<*const u8, unsafe extern "C" fn()>(
-                        ManuallyDrop::new(mmap).as_ptr(),
-                    )
+                CompiledFunction {
+                    function: unsafe {
+                        std::mem::transmute::<*const u8, unsafe extern "C" fn(*mut u8)>(
+                            ManuallyDrop::new(mmap).as_ptr(),
+                        )
+                    },
+                    call_depth_change: mnemonic.call_depth_change(),
                 },
                 is_prg_rom_only,
             )
         };
 
         let function = {
+            let mut prg_rom_functions = self.prg_rom_functions.borrow_mut();
             let mut dummy_entry = None;
             let entry = if pc >= 0x8000 {
-                let prg_rom_functions_len = self.prg_rom_functions.len();
-                self.prg_rom_functions
+                let prg_rom_functions_len = prg_rom_functions.len();
+                prg_rom_functions
                     .get_mut(usize::from(pc) & (prg_rom_functions_len - 1))
                     .unwrap()
             } else {
@@ -76,8 +324,250 @@ impl<Cartridge: cartridge::Cartridge> Nopt<Cartridge> {
         };
 
         trace!("running with pc: 0x{pc:04x}");
+        // compiled code only accesses the machine state through the given
+        // pointer, so it stays valid wherever the state is moved to
         unsafe {
-            function();
+            (function.function)((&raw mut self.nes).cast());
+        }
+        function.call_depth_change
+    }
+
+    fn finish_instruction(&mut self) -> bool {
+        let is_interrupted = self.nes.finish_instruction();
+        if let Some(state_hash_log) = &mut self.state_hash_log {
+            state_hash_log.update(&self.nes);
         }
+        is_interrupted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use watchpoint::Access;
+
+    // SEI; loop: INC $00; LDA $00; STA $0200,X; INX; JMP loop
+    const PROGRAM: [u8; 12] = [
+        0x78, 0xe6, 0x00, 0xa5, 0x00, 0x9d, 0x00, 0x02, 0xe8, 0x4c, 0x01, 0x80,
+    ];
+
+    #[test]
+    fn compiled_code_runs_on_moved_state() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        let mut interpreted_nopt = test_utils::nopt(&PROGRAM);
+        interpreted_nopt.set_jit_enabled(false);
+        for _ in 0..100 {
+            nopt.run();
+            interpreted_nopt.run();
+        }
+
+        // the state moves to the heap, while the code compiled for it stays
+        let mut nopt = Box::new(nopt);
+        for _ in 0..100 {
+            nopt.run();
+            interpreted_nopt.run();
+        }
+        assert_eq!(nopt.save_state(), interpreted_nopt.save_state());
+    }
+
+    #[test]
+    fn forks_run_independently_sharing_compiled_code() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        nopt.run_frame();
+        let state = nopt.save_state();
+
+        let mut fork = nopt.fork();
+        assert!(Rc::ptr_eq(&nopt.prg_rom_functions, &fork.prg_rom_functions));
+        fork.run_frame();
+        assert_eq!(nopt.save_state(), state);
+        assert_ne!(fork.save_state(), state);
+        nopt.run_frame();
+        assert_eq!(nopt.save_state(), fork.save_state());
+
+        // cheats are baked into compiled code, so the fork stops sharing it
+        fork.set_cheats(vec!["8003=a9".parse().unwrap()]);
+        assert!(!Rc::ptr_eq(
+            &nopt.prg_rom_functions,
+            &fork.prg_rom_functions
+        ));
+        assert!(nopt.prg_rom_functions.borrow()[1].is_some());
+    }
+
+    #[test]
+    fn loading_state_keeps_shared_compiled_code() {
+        // SEI; loop: INC $00; JMP loop
+        let mut nopt = test_utils::nopt(&[0x78, 0xe6, 0x00, 0x4c, 0x01, 0x80]);
+        nopt.run_frame();
+        let state = nopt.save_state();
+        nopt.run_frame();
+
+        let mut fork = nopt.fork();
+        fork.load_state(&state).unwrap();
+        assert!(nopt.prg_rom_functions.borrow()[1].is_some());
+        fork.run_frame();
+        assert_eq!(fork.save_state(), nopt.save_state());
+    }
+
+    #[test]
+    fn breakpoints_stop_before_instructions() {
+        for is_jit_enabled in [false, true] {
+            let mut nopt = test_utils::nopt(&PROGRAM);
+            nopt.set_jit_enabled(is_jit_enabled);
+            nopt.add_breakpoint(0x8000);
+            assert_eq!(nopt.run(), Some(Stopped::Breakpoint(0x8000)));
+            assert_eq!(nopt.nes().cpu.pc, 0x8000);
+            assert_eq!(nopt.run(), None);
+            assert_eq!(nopt.nes().cpu.pc, 0x8001);
+
+            nopt.add_breakpoint(0x8003);
+            assert_eq!(nopt.run(), Some(Stopped::Breakpoint(0x8003)));
+            assert_eq!(nopt.nes().cpu.ram[0], 1);
+            assert_eq!(nopt.run(), None);
+            assert_eq!(nopt.run_frame(), Some(Stopped::Breakpoint(0x8003)));
+            assert_eq!(nopt.nes().cpu.ram[0], 2);
+
+            assert!(nopt.remove_breakpoint(0x8003));
+            assert!(!nopt.remove_breakpoint(0x8003));
+            assert_eq!(nopt.run_frame(), None);
+        }
+    }
+
+    #[test]
+    fn breakpoints_at_current_instruction_stop_unless_resuming() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        while nopt.nes().cpu.pc != 0x8003 {
+            nopt.run();
+        }
+        let state = nopt.save_state();
+        nopt.add_breakpoint(0x8003);
+        assert_eq!(nopt.run(), Some(Stopped::Breakpoint(0x8003)));
+        assert_eq!(nopt.save_state(), state);
+        assert_eq!(nopt.run(), None);
+
+        nopt.load_state(&state).unwrap();
+        assert_eq!(nopt.run(), Some(Stopped::Breakpoint(0x8003)));
+        assert_eq!(nopt.save_state(), state);
+    }
+
+    #[test]
+    fn breakpoints_after_watchpoints_stop() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        nopt.add_breakpoint(0x8003);
+        nopt.set_watchpoints(vec![Watchpoint {
+            addresses: 0x0000..=0x0000,
+            access: Access::Write,
+            value: None,
+        }]);
+        nopt.run();
+        assert_eq!(
+            nopt.run(),
+            Some(Stopped::Watchpoint {
+                address: 0x0000,
+                value: 1,
+                pc: 0x8001,
+            })
+        );
+        assert_eq!(nopt.run(), Some(Stopped::Breakpoint(0x8003)));
+        assert_eq!(nopt.nes().cpu.pc, 0x8003);
+        assert_eq!(nopt.run(), None);
+    }
+
+    // SEI; JSR subroutine; INC $01; loop: JMP loop; NOP;
+    // subroutine: INC $00; JSR inner; INC $02; RTS;
+    // inner: INC $03; RTS
+    const CALLING_PROGRAM: [u8; 21] = [
+        0x78, 0x20, 0x0a, 0x80, 0xe6, 0x01, 0x4c, 0x06, 0x80, 0xea, 0xe6, 0x00, 0x20, 0x12, 0x80,
+        0xe6, 0x02, 0x60, 0xe6, 0x03, 0x60,
+    ];
+
+    fn calling_nopts() -> impl Iterator<Item = Nopt<cartridge::Nrom>> {
+        [false, true].into_iter().map(|is_jit_enabled| {
+            let mut nopt = test_utils::nopt(&CALLING_PROGRAM);
+            nopt.set_jit_enabled(is_jit_enabled);
+            nopt
+        })
+    }
+
+    #[test]
+    fn step_over_skips_subroutines() {
+        for mut nopt in calling_nopts() {
+            assert_eq!(nopt.step_over(), None);
+            assert_eq!(nopt.nes().cpu.pc, 0x8001);
+            assert_eq!(nopt.step_over(), None);
+            assert_eq!((nopt.nes().cpu.pc, nopt.call_depth()), (0x8004, 0));
+            assert_eq!(nopt.nes().cpu.ram[..4], [1, 0, 1, 1]);
+            assert_eq!(nopt.step_over(), None);
+            assert_eq!(nopt.nes().cpu.pc, 0x8006);
+            assert_eq!(nopt.nes().cpu.ram[..4], [1, 1, 1, 1]);
+        }
+    }
+
+    #[test]
+    fn step_over_stops_at_breakpoints() {
+        for mut nopt in calling_nopts() {
+            nopt.add_breakpoint(0x8012);
+            nopt.step_instruction();
+            assert_eq!(nopt.step_over(), Some(Stopped::Breakpoint(0x8012)));
+            assert_eq!(nopt.call_depth(), 2);
+        }
+    }
+
+    #[test]
+    fn step_out_stops_after_matching_return() {
+        for mut nopt in calling_nopts() {
+            for _ in 0..4 {
+                nopt.step_instruction();
+            }
+            assert_eq!((nopt.nes().cpu.pc, nopt.call_depth()), (0x8012, 2));
+            assert_eq!(nopt.step_out(), None);
+            assert_eq!((nopt.nes().cpu.pc, nopt.call_depth()), (0x800f, 1));
+            assert_eq!(nopt.nes().cpu.ram[..4], [1, 0, 0, 1]);
+            assert_eq!(nopt.step_out(), None);
+            assert_eq!((nopt.nes().cpu.pc, nopt.call_depth()), (0x8004, 0));
+            assert_eq!(nopt.nes().cpu.ram[..4], [1, 0, 1, 1]);
+        }
+    }
+
+    #[test]
+    fn cached_compiled_code_tracks_call_depth() {
+        // SEI; loop: JSR subroutine; JMP loop; subroutine: RTS
+        let mut nopt = test_utils::nopt(&[0x78, 0x20, 0x07, 0x80, 0x4c, 0x01, 0x80, 0x60]);
+        nopt.run();
+        for _ in 0..3 {
+            for (pc, call_depth) in [(0x8007, 1), (0x8004, 0), (0x8001, 0)] {
+                nopt.run();
+                assert_eq!((nopt.nes().cpu.pc, nopt.call_depth()), (pc, call_depth));
+            }
+        }
+        assert!(nopt.prg_rom_functions.borrow()[7].is_some());
+    }
+
+    #[test]
+    fn compiled_functions_run_single_instructions() {
+        // breakpoints are only checked between functions, so stepping relies
+        // on this until compilation stops at breakpoints
+        // SEI; LDA #$01; LDX $00; INX; STX $0200; NOP; loop: JMP loop
+        let program = [
+            0x78, 0xa9, 0x01, 0xa6, 0x00, 0xe8, 0x8e, 0x00, 0x02, 0xea, 0x4c, 0x0a, 0x80,
+        ];
+        let mut nopt = test_utils::nopt(&program);
+        for pc in [0x8001, 0x8003, 0x8005, 0x8006, 0x8009, 0x800a, 0x800a] {
+            nopt.run();
+            assert_eq!(nopt.nes().cpu.pc, pc);
+        }
+    }
+
+    #[test]
+    fn adding_breakpoints_discards_compiled_code() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        nopt.run_frame();
+        let mut fork = nopt.fork();
+        fork.add_breakpoint(0x8009);
+        assert!(!Rc::ptr_eq(
+            &nopt.prg_rom_functions,
+            &fork.prg_rom_functions
+        ));
+        assert_eq!(fork.run_frame(), Some(Stopped::Breakpoint(0x8009)));
     }
 }
@@ -0,0 +1,345 @@
+use crate::{Buttons, Nopt, cartridge, save_state::LoadStateError};
+use std::{fmt, fmt::Write as _};
+
+/// A recording of the controller input of every frame, starting either from
+/// power-on or from a save state.
+///
+/// A frame lasts from the start of one vblank to the next, as run by
+/// [`Nopt::run_frame`], with the controller state set right before it is run.
+/// The input devices are assumed to be standard controllers.
+#[derive(Clone, Default)]
+pub struct Movie {
+    save_state: Option<Vec<u8>>,
+    inputs: Vec<[Buttons; 2]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fm2Error {
+    /// The first line does not declare version 3 of the format.
+    UnsupportedVersion,
+    /// A line could not be parsed, counting from 1.
+    InvalidLine(usize),
+    /// A frame requests a command such as a reset, counting from 1.
+    UnsupportedCommand(usize),
+    /// The movie uses input devices other than standard controllers.
+    UnsupportedDevice,
+    /// The movie starts from a save state in an unsupported encoding.
+    UnsupportedSaveState,
+}
+
+impl fmt::Display for Fm2Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion => write!(formatter, "unsupported FM2 version"),
+            Self::InvalidLine(line) => write!(formatter, "invalid FM2 line {line}"),
+            Self::UnsupportedCommand(line) => {
+                write!(formatter, "unsupported command on FM2 line {line}")
+            }
+            Self::UnsupportedDevice => write!(formatter, "unsupported FM2 input device"),
+            Self::UnsupportedSaveState => write!(formatter, "unsupported FM2 save state"),
+        }
+    }
+}
+
+impl std::error::Error for Fm2Error {}
+
+/// The buttons in the order they appear in an FM2 input field, from bit 7 to
+/// bit 0 of [`Buttons`].
+const FM2_BUTTON_CHARACTERS: [char; 8] = ['R', 'L', 'D', 'U', 'T', 'S', 'B', 'A'];
+
+impl Movie {
+    /// Creates an empty movie starting from power-on.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty movie starting from the current state of the machine.
+    #[must_use]
+    pub fn from_current_state<Cartridge: cartridge::Cartridge>(nopt: &Nopt<Cartridge>) -> Self {
+        Self {
+            save_state: Some(nopt.save_state()),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// The save state the movie starts from, if it does not start from
+    /// power-on.
+    #[must_use]
+    pub fn save_state(&self) -> Option<&[u8]> {
+        self.save_state.as_deref()
+    }
+
+    /// The controller states of each frame.
+    #[must_use]
+    pub fn inputs(&self) -> &[[Buttons; 2]] {
+        &self.inputs
+    }
+
+    /// Runs a single frame with the given controller states, appending them to
+    /// the movie.
+    pub fn record_frame<Cartridge: cartridge::Cartridge>(
+        &mut self,
+        nopt: &mut Nopt<Cartridge>,
+        buttons: [Buttons; 2],
+    ) {
+        nopt.run_frame_with_buttons(buttons);
+        self.inputs.push(buttons);
+    }
+
+    /// Prepares the machine for playback, loading the save state the movie
+    /// starts from. A movie starting from power-on should be played back on a
+    /// newly created machine.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the save state cannot be loaded into the machine.
+    pub fn play<Cartridge: cartridge::Cartridge>(
+        &self,
+        nopt: &mut Nopt<Cartridge>,
+    ) -> Result<Playback<'_>, LoadStateError> {
+        if let Some(save_state) = &self.save_state {
+            nopt.load_state(save_state)?;
+        }
+        Ok(Playback {
+            movie: self,
+            frame: 0,
+        })
+    }
+
+    /// Exports the movie in the FM2 format of FCEUX. A save state is stored in
+    /// this crate's own format, so such movies can only be played back here.
+    ///
+    /// The ROM checksum is written as given, FCEUX expecting the MD5 hash of
+    /// the ROM encoded as `base64:` followed by its base64 representation. The
+    /// GUID identifies the recording, such as
+    /// `452DE2C3-EF43-2FA9-77AC-0677FC51543B`.
+    #[must_use]
+    pub fn to_fm2(&self, rom_filename: &str, rom_checksum: &str, guid: &str) -> String {
+        let mut fm2 = String::new();
+        writeln!(fm2, "version 3").unwrap();
+        writeln!(fm2, "emuVersion 0").unwrap();
+        writeln!(fm2, "palFlag 0").unwrap();
+        writeln!(fm2, "romFilename {rom_filename}").unwrap();
+        writeln!(fm2, "romChecksum {rom_checksum}").unwrap();
+        writeln!(fm2, "guid {guid}").unwrap();
+        writeln!(fm2, "fourscore 0").unwrap();
+        writeln!(fm2, "port0 1").unwrap();
+        writeln!(fm2, "port1 1").unwrap();
+        writeln!(fm2, "port2 0").unwrap();
+        if let Some(save_state) = &self.save_state {
+            write!(fm2, "savestate 0x").unwrap();
+            for byte in save_state {
+                write!(fm2, "{byte:02x}").unwrap();
+            }
+            writeln!(fm2).unwrap();
+        }
+
+        for buttons in &self.inputs {
+            write!(fm2, "|0").unwrap();
+            for buttons in buttons {
+                write!(fm2, "|").unwrap();
+                for (character_index, character) in FM2_BUTTON_CHARACTERS.iter().enumerate() {
+                    let is_pressed = buttons.bits() & (0b1000_0000 >> character_index) != 0;
+                    fm2.push(if is_pressed { *character } else { '.' });
+                }
+            }
+            writeln!(fm2, "||").unwrap();
+        }
+        fm2
+    }
+
+    /// Imports a movie in the FM2 format of FCEUX.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the movie is malformed, or relies on features
+    /// which are not supported, such as resets or other input devices.
+    pub fn from_fm2(fm2: &str) -> Result<Self, Fm2Error> {
+        let mut lines = fm2
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        if lines.next().map(|(_, line)| line.trim_end()) != Some("version 3") {
+            return Err(Fm2Error::UnsupportedVersion);
+        }
+
+        let mut movie = Self::new();
+        let mut is_four_score_used = false;
+        for (line_number, line) in lines {
+            if line.starts_with('|') {
+                let buttons = Self::parse_fm2_input(line, line_number, is_four_score_used)?;
+                movie.inputs.push(buttons);
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once(' ') else {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Err(Fm2Error::InvalidLine(line_number));
+            };
+            match (key, value.trim_end()) {
+                ("fourscore", value) => is_four_score_used = value == "1",
+                ("port0" | "port1", "0" | "1") | ("port2", "0") => {}
+                ("port0" | "port1" | "port2", _) => return Err(Fm2Error::UnsupportedDevice),
+                ("savestate", value) => {
+                    let Some(hex) = value.strip_prefix("0x") else {
+                        return Err(Fm2Error::UnsupportedSaveState);
+                    };
+                    movie.save_state =
+                        Some(Self::parse_hex(hex).ok_or(Fm2Error::InvalidLine(line_number))?);
+                }
+                _ => {}
+            }
+        }
+        Ok(movie)
+    }
+
+    fn parse_fm2_input(
+        line: &str,
+        line_number: usize,
+        is_four_score_used: bool,
+    ) -> Result<[Buttons; 2], Fm2Error> {
+        let mut fields = line.split('|').skip(1);
+        let commands = fields.next().ok_or(Fm2Error::InvalidLine(line_number))?;
+        if commands
+            .trim()
+            .parse::<u8>()
+            .map_err(|_| Fm2Error::InvalidLine(line_number))?
+            != 0
+        {
+            return Err(Fm2Error::UnsupportedCommand(line_number));
+        }
+
+        let controller_count = if is_four_score_used { 4 } else { 2 };
+        let fields = fields.take(controller_count).collect::<Vec<_>>();
+        if fields.len() != controller_count {
+            return Err(Fm2Error::InvalidLine(line_number));
+        }
+
+        // with the Four Score, only the first controller of each port is
+        // supported
+        let is_pressed = |character| !matches!(character, '.' | ' ');
+        if fields[2..]
+            .iter()
+            .any(|field| field.chars().any(is_pressed))
+        {
+            return Err(Fm2Error::UnsupportedDevice);
+        }
+
+        let mut buttons = [Buttons::empty(); 2];
+        for (field, buttons) in fields.iter().zip(&mut buttons) {
+            if field.is_empty() {
+                continue;
+            }
+            if field.chars().count() != FM2_BUTTON_CHARACTERS.len() {
+                return Err(Fm2Error::InvalidLine(line_number));
+            }
+            for (character_index, character) in field.chars().enumerate() {
+                if is_pressed(character) {
+                    *buttons |= Buttons::from_bits_retain(0b1000_0000 >> character_index);
+                }
+            }
+        }
+        Ok(buttons)
+    }
+
+    fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+            .collect()
+    }
+}
+
+/// Plays back the frames of a [`Movie`].
+pub struct Playback<'a> {
+    movie: &'a Movie,
+    frame: usize,
+}
+
+impl Playback<'_> {
+    /// The number of frames played back so far.
+    #[must_use]
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.frame == self.movie.inputs.len()
+    }
+
+    /// Runs the next frame of the movie, returning whether there was one.
+    pub fn run_frame<Cartridge: cartridge::Cartridge>(
+        &mut self,
+        nopt: &mut Nopt<Cartridge>,
+    ) -> bool {
+        let Some(buttons) = self.movie.inputs.get(self.frame) else {
+            return false;
+        };
+        nopt.run_frame_with_buttons(*buttons);
+        self.frame += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: &str = "452DE2C3-EF43-2FA9-77AC-0677FC51543B";
+
+    #[test]
+    fn fm2_round_trips() {
+        let movie = Movie {
+            save_state: Some(vec![0x00, 0x12, 0xff]),
+            inputs: vec![
+                [Buttons::empty(), Buttons::empty()],
+                [Buttons::A | Buttons::RIGHT, Buttons::START],
+                [Buttons::all(), Buttons::UP | Buttons::SELECT],
+            ],
+        };
+        let fm2 = movie.to_fm2("game.nes", "base64:1B2M2Y8AsgTpgAmY7PhCfg==", GUID);
+        assert!(fm2.contains("\nromFilename game.nes\n"));
+        assert!(fm2.contains("\nromChecksum base64:1B2M2Y8AsgTpgAmY7PhCfg==\n"));
+        assert!(fm2.contains(&format!("\nguid {GUID}\n")));
+        assert!(fm2.contains("\n|0|R......A|....T...||\n"));
+
+        let imported = Movie::from_fm2(&fm2).unwrap();
+        assert_eq!(imported.save_state(), movie.save_state());
+        assert_eq!(imported.inputs(), movie.inputs());
+    }
+
+    #[test]
+    fn fm2_with_four_score_is_imported() {
+        let fm2 = "version 3\nfourscore 1\nport2 0\n|0|.......A|......B.|........|........||\n";
+        let movie = Movie::from_fm2(fm2).unwrap();
+        assert_eq!(movie.save_state(), None);
+        assert_eq!(movie.inputs(), [[Buttons::A, Buttons::B]]);
+    }
+
+    #[test]
+    fn unsupported_fm2_is_rejected() {
+        assert_eq!(
+            Movie::from_fm2("version 2\n").err(),
+            Some(Fm2Error::UnsupportedVersion)
+        );
+        assert_eq!(
+            Movie::from_fm2("version 3\nport0 2\n").err(),
+            Some(Fm2Error::UnsupportedDevice)
+        );
+        assert_eq!(
+            Movie::from_fm2("version 3\n|1|........|........||\n").err(),
+            Some(Fm2Error::UnsupportedCommand(2))
+        );
+        assert_eq!(
+            Movie::from_fm2("version 3\n|0|....|........||\n").err(),
+            Some(Fm2Error::InvalidLine(2))
+        );
+    }
+}
@@ -365,6 +365,85 @@ impl Operation {
     pub(crate) fn len(self) -> u8 {
         1 + self.addressing_mode.len()
     }
+
+    /// The number of CPU cycles taken by the operation, excluding the extra
+    /// cycles of page crossings and taken branches.
+    #[expect(clippy::match_same_arms)]
+    pub(crate) fn cycles(self) -> u8 {
+        match (self.mnemonic, self.addressing_mode) {
+            (Mnemonic::Brk, _) => 7,
+            (Mnemonic::Jsr | Mnemonic::Rti | Mnemonic::Rts, _) => 6,
+            (Mnemonic::Pha | Mnemonic::Php, _) => 3,
+            (Mnemonic::Pla | Mnemonic::Plp, _) => 4,
+            (Mnemonic::Jmp, AddressingMode::Absolute) => 3,
+            (
+                Mnemonic::Asl
+                | Mnemonic::Dec
+                | Mnemonic::Inc
+                | Mnemonic::Lsr
+                | Mnemonic::Rol
+                | Mnemonic::Ror,
+                AddressingMode::Zeropage,
+            ) => 5,
+            (
+                Mnemonic::Asl
+                | Mnemonic::Dec
+                | Mnemonic::Inc
+                | Mnemonic::Lsr
+                | Mnemonic::Rol
+                | Mnemonic::Ror,
+                AddressingMode::ZeropageX | AddressingMode::Absolute,
+            ) => 6,
+            (
+                Mnemonic::Asl
+                | Mnemonic::Dec
+                | Mnemonic::Inc
+                | Mnemonic::Lsr
+                | Mnemonic::Rol
+                | Mnemonic::Ror,
+                AddressingMode::AbsoluteX,
+            ) => 7,
+            (
+                Mnemonic::Sta | Mnemonic::Stx | Mnemonic::Sty,
+                AddressingMode::AbsoluteX | AddressingMode::AbsoluteY,
+            ) => 5,
+            (Mnemonic::Sta | Mnemonic::Stx | Mnemonic::Sty, AddressingMode::IndirectY) => 6,
+            (_, addressing_mode) => match addressing_mode {
+                AddressingMode::Accumulator
+                | AddressingMode::Immediate
+                | AddressingMode::Implied
+                | AddressingMode::Relative => 2,
+                AddressingMode::Zeropage => 3,
+                AddressingMode::Absolute
+                | AddressingMode::AbsoluteX
+                | AddressingMode::AbsoluteY
+                | AddressingMode::ZeropageX
+                | AddressingMode::ZeropageY => 4,
+                AddressingMode::Indirect | AddressingMode::IndirectY => 5,
+                AddressingMode::XIndirect => 6,
+            },
+        }
+    }
+
+    /// Whether the operation takes an extra cycle when its indexed operand
+    /// address crosses a page boundary.
+    pub(crate) fn has_page_crossing_penalty(self) -> bool {
+        matches!(
+            self.addressing_mode,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectY
+        ) && matches!(
+            self.mnemonic,
+            Mnemonic::Adc
+                | Mnemonic::And
+                | Mnemonic::Cmp
+                | Mnemonic::Eor
+                | Mnemonic::Lda
+                | Mnemonic::Ldx
+                | Mnemonic::Ldy
+                | Mnemonic::Ora
+                | Mnemonic::Sbc
+        )
+    }
 }
 
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -428,6 +507,18 @@ pub(crate) enum Mnemonic {
     Unimplemented,
 }
 
+impl Mnemonic {
+    /// How running the operation changes the number of subroutines and
+    /// interrupt handlers entered but not yet returned from.
+    pub(crate) fn call_depth_change(self) -> i64 {
+        match self {
+            Self::Brk | Self::Jsr => 1,
+            Self::Rti | Self::Rts => -1,
+            _ => 0,
+        }
+    }
+}
+
 impl std::fmt::Debug for Mnemonic {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -527,3 +618,35 @@ impl AddressingMode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles() {
+        for (opcode, cycles, has_page_crossing_penalty) in [
+            (0xa9, 2, false), // LDA #imm
+            (0xa5, 3, false), // LDA zp
+            (0xbd, 4, true),  // LDA abs,X
+            (0xb1, 5, true),  // LDA (zp),Y
+            (0xa1, 6, false), // LDA (zp,X)
+            (0x9d, 5, false), // STA abs,X
+            (0x91, 6, false), // STA (zp),Y
+            (0xfe, 7, false), // INC abs,X
+            (0x4c, 3, false), // JMP abs
+            (0x6c, 5, false), // JMP (abs)
+            (0x20, 6, false), // JSR
+            (0x00, 7, false), // BRK
+            (0xd0, 2, false), // BNE
+        ] {
+            let operation = Operation::from_opcode(opcode);
+            assert_eq!(operation.cycles(), cycles, "opcode 0x{opcode:02x}");
+            assert_eq!(
+                operation.has_page_crossing_penalty(),
+                has_page_crossing_penalty,
+                "opcode 0x{opcode:02x}"
+            );
+        }
+    }
+}
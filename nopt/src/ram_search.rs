@@ -0,0 +1,224 @@
+use crate::{Nopt, cartridge};
+use std::ops::RangeInclusive;
+
+/// The address ranges searched: CPU RAM, then PRG RAM.
+const SEARCHED_ADDRESSES: [RangeInclusive<u16>; 2] = [0x0000..=0x07ff, 0x6000..=0x7fff];
+
+/// How the value at an address must relate to its value in the previous
+/// snapshot for the address to remain a candidate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    EqualTo(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    /// Increased by exactly the given amount, wrapping around.
+    IncreasedBy(u8),
+    /// Decreased by exactly the given amount, wrapping around.
+    DecreasedBy(u8),
+}
+
+impl Comparison {
+    fn matches(self, previous_value: u8, value: u8) -> bool {
+        match self {
+            Self::EqualTo(expected_value) => value == expected_value,
+            Self::Changed => value != previous_value,
+            Self::Unchanged => value == previous_value,
+            Self::Increased => value > previous_value,
+            Self::Decreased => value < previous_value,
+            Self::IncreasedBy(amount) => value == previous_value.wrapping_add(amount),
+            Self::DecreasedBy(amount) => value == previous_value.wrapping_sub(amount),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub address: u16,
+    /// The value at the address when the latest snapshot was taken.
+    pub value: u8,
+}
+
+/// Narrows down the RAM addresses holding some value of interest, e.g. the
+/// number of lives, by repeatedly comparing the RAM against snapshots of it.
+#[derive(Clone)]
+pub struct RamSearch {
+    candidates: Vec<Candidate>,
+}
+
+impl RamSearch {
+    /// Starts a search with every address of CPU RAM and PRG RAM as a
+    /// candidate, snapshotting their current values.
+    #[must_use]
+    pub fn new<Cartridge: cartridge::Cartridge>(nopt: &Nopt<Cartridge>) -> Self {
+        let candidates = SEARCHED_ADDRESSES
+            .into_iter()
+            .flatten()
+            .map(|address| Candidate {
+                address,
+                value: nopt.nes().peek(address),
+            })
+            .collect();
+        Self { candidates }
+    }
+
+    /// The remaining candidates, in order of address.
+    #[must_use]
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    /// Keeps only the candidates whose current value relates to the previous
+    /// snapshot as given, then takes a new snapshot of them.
+    pub fn filter<Cartridge: cartridge::Cartridge>(
+        &mut self,
+        nopt: &Nopt<Cartridge>,
+        comparison: Comparison,
+    ) {
+        self.candidates.retain_mut(|candidate| {
+            let value = nopt.nes().peek(candidate.address);
+            let is_match = comparison.matches(candidate.value, value);
+            candidate.value = value;
+            is_match
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    // SEI; loop: INC $6005; JMP loop
+    const PROGRAM: [u8; 7] = [0x78, 0xee, 0x05, 0x60, 0x4c, 0x01, 0x80];
+
+    #[test]
+    fn comparisons_match() {
+        let cases = [
+            (Comparison::EqualTo(3), [(1, 3), (3, 3)], [(3, 1), (3, 4)]),
+            (Comparison::Changed, [(1, 2), (2, 1)], [(0, 0), (5, 5)]),
+            (Comparison::Unchanged, [(0, 0), (5, 5)], [(1, 2), (2, 1)]),
+            (
+                Comparison::Increased,
+                [(1, 2), (0, 0xff)],
+                [(2, 2), (0xff, 0)],
+            ),
+            (
+                Comparison::Decreased,
+                [(2, 1), (0xff, 0)],
+                [(2, 2), (0, 0xff)],
+            ),
+            (
+                Comparison::IncreasedBy(2),
+                [(1, 3), (0xff, 1)],
+                [(1, 2), (3, 1)],
+            ),
+            (
+                Comparison::DecreasedBy(2),
+                [(3, 1), (1, 0xff)],
+                [(3, 2), (1, 3)],
+            ),
+        ];
+        for (comparison, matches, mismatches) in cases {
+            for (previous_value, value) in matches {
+                assert!(comparison.matches(previous_value, value), "{comparison:?}");
+            }
+            for (previous_value, value) in mismatches {
+                assert!(!comparison.matches(previous_value, value), "{comparison:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn search_starts_with_cpu_and_prg_ram() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        nopt.nes_mut().cpu.ram[0x07ff] = 4;
+        let ram_search = RamSearch::new(&nopt);
+        let candidates = ram_search.candidates();
+        assert_eq!(candidates.len(), 0x800 + 0x2000);
+        assert!(
+            candidates
+                .windows(2)
+                .all(|candidates| candidates[0].address < candidates[1].address)
+        );
+        assert_eq!(
+            candidates[0x7ff..=0x800],
+            [
+                Candidate {
+                    address: 0x07ff,
+                    value: 4,
+                },
+                Candidate {
+                    address: 0x6000,
+                    value: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_narrow_down_candidates() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        let mut ram_search = RamSearch::new(&nopt);
+        nopt.nes_mut().cpu.ram[0x10] = 5;
+        nopt.nes_mut().cpu.ram[0x20] = 7;
+        ram_search.filter(&nopt, Comparison::Changed);
+        assert_eq!(
+            ram_search.candidates(),
+            [
+                Candidate {
+                    address: 0x10,
+                    value: 5,
+                },
+                Candidate {
+                    address: 0x20,
+                    value: 7,
+                },
+            ]
+        );
+
+        // the snapshot is updated, so an unchanged value now matches
+        ram_search.filter(&nopt, Comparison::Unchanged);
+        assert_eq!(ram_search.candidates().len(), 2);
+
+        nopt.nes_mut().cpu.ram[0x10] = 4;
+        nopt.nes_mut().cpu.ram[0x20] = 6;
+        nopt.nes_mut().cpu.ram[0x30] = 1;
+        ram_search.filter(&nopt, Comparison::DecreasedBy(1));
+        assert_eq!(ram_search.candidates().len(), 2);
+        ram_search.filter(&nopt, Comparison::EqualTo(6));
+        assert_eq!(
+            ram_search.candidates(),
+            [Candidate {
+                address: 0x20,
+                value: 6,
+            }]
+        );
+    }
+
+    #[test]
+    fn prg_ram_is_searched() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        let mut ram_search = RamSearch::new(&nopt);
+        for _ in 0..2 {
+            nopt.step_instruction();
+        }
+        ram_search.filter(&nopt, Comparison::IncreasedBy(1));
+        assert_eq!(
+            ram_search.candidates(),
+            [Candidate {
+                address: 0x6005,
+                value: 1,
+            }]
+        );
+
+        for _ in 0..2 {
+            nopt.step_instruction();
+        }
+        ram_search.filter(&nopt, Comparison::Increased);
+        assert_eq!(ram_search.candidates().len(), 1);
+        ram_search.filter(&nopt, Comparison::Changed);
+        assert!(ram_search.candidates().is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::{Buttons, Nopt, cartridge};
+use std::collections::VecDeque;
+
+/// Records the recent history of a machine, allowing it to be stepped
+/// backwards frame by frame.
+///
+/// Every `snapshot_interval` frames, a snapshot of the machine is taken,
+/// which unlike a save state leaves out the picture and the input devices.
+/// Only the latest one is kept whole, with each older one stored as the
+/// difference to its successor. The inputs of every frame are recorded as
+/// well, so that any frame between two snapshots can be reached by restoring
+/// the earlier one and running the machine forward again. The input devices
+/// are assumed to be standard controllers.
+pub struct Rewind {
+    snapshot_interval: u64,
+    memory_limit: usize,
+    /// The number of frames run since recording started.
+    frame: u64,
+    latest_snapshot: Option<Snapshot>,
+    /// Older snapshots from oldest to newest.
+    older_snapshots: VecDeque<EncodedSnapshot>,
+    /// The inputs of each frame from the oldest snapshot onwards.
+    inputs: VecDeque<[Buttons; 2]>,
+}
+
+struct Snapshot {
+    frame: u64,
+    data: Vec<u8>,
+}
+
+struct EncodedSnapshot {
+    frame: u64,
+    encoding: Encoding,
+}
+
+enum Encoding {
+    Keyframe(Vec<u8>),
+    /// The difference to the following snapshot, see [`encode_delta`].
+    Delta(Vec<u8>),
+}
+
+impl EncodedSnapshot {
+    fn decode(&self, next_snapshot: &Snapshot) -> Snapshot {
+        Snapshot {
+            frame: self.frame,
+            data: match &self.encoding {
+                Encoding::Keyframe(data) => data.clone(),
+                Encoding::Delta(delta) => apply_delta(&next_snapshot.data, delta),
+            },
+        }
+    }
+
+    fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Keyframe(data) | Encoding::Delta(data) => data.len(),
+        }
+    }
+}
+
+impl Rewind {
+    /// Creates a recorder taking a snapshot every `snapshot_interval` frames,
+    /// which discards the oldest history once it uses more than
+    /// `memory_limit` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `snapshot_interval` is zero.
+    #[must_use]
+    pub fn new(snapshot_interval: u64, memory_limit: usize) -> Self {
+        assert!(snapshot_interval > 0, "snapshot interval must be positive");
+        Self {
+            snapshot_interval,
+            memory_limit,
+            frame: 0,
+            latest_snapshot: None,
+            older_snapshots: VecDeque::new(),
+            inputs: VecDeque::new(),
+        }
+    }
+
+    /// The number of frames which can currently be stepped back.
+    #[must_use]
+    pub fn available_frames(&self) -> u64 {
+        self.oldest_frame()
+            .map_or(0, |oldest_frame| self.frame - oldest_frame)
+    }
+
+    /// The number of bytes used by the recorded history.
+    #[must_use]
+    pub fn memory_usage(&self) -> usize {
+        self.latest_snapshot
+            .iter()
+            .map(|snapshot| snapshot.data.len())
+            .chain(self.older_snapshots.iter().map(EncodedSnapshot::len))
+            .sum::<usize>()
+            + self.inputs.len() * size_of::<[Buttons; 2]>()
+    }
+
+    /// Runs a single frame with the given controller states, recording it.
+    pub fn run_frame<Cartridge: cartridge::Cartridge>(
+        &mut self,
+        nopt: &mut Nopt<Cartridge>,
+        buttons: [Buttons; 2],
+    ) {
+        // a snapshot of the frame may remain from before stepping back
+        let is_snapshot_taken = self
+            .latest_snapshot
+            .as_ref()
+            .is_some_and(|snapshot| snapshot.frame == self.frame);
+        if self.frame.is_multiple_of(self.snapshot_interval) && !is_snapshot_taken {
+            self.take_snapshot(nopt);
+        }
+        if self.latest_snapshot.is_some() {
+            self.inputs.push_back(buttons);
+        }
+
+        nopt.run_frame_with_buttons(buttons);
+        self.frame += 1;
+    }
+
+    /// Returns the machine to the start of the previous frame, forgetting the
+    /// frame undone. Returns whether there was a frame to step back to.
+    pub fn step_back<Cartridge: cartridge::Cartridge>(
+        &mut self,
+        nopt: &mut Nopt<Cartridge>,
+    ) -> bool {
+        if self.available_frames() == 0 {
+            return false;
+        }
+        let target_frame = self.frame - 1;
+
+        // discard the snapshots taken after the target frame
+        while self
+            .latest_snapshot
+            .as_ref()
+            .is_some_and(|snapshot| snapshot.frame > target_frame)
+        {
+            let latest_snapshot = self.latest_snapshot.take().unwrap();
+            self.latest_snapshot = self
+                .older_snapshots
+                .pop_back()
+                .map(|snapshot| snapshot.decode(&latest_snapshot));
+        }
+        let latest_snapshot = self.latest_snapshot.as_ref().unwrap();
+
+        // the picture is not part of snapshots, so the frame before the
+        // target one is run again to render it, unless it is not recorded
+        let previous_snapshot = self
+            .older_snapshots
+            .back()
+            .filter(|_| latest_snapshot.frame == target_frame)
+            .map(|snapshot| snapshot.decode(latest_snapshot));
+        let snapshot = previous_snapshot.as_ref().unwrap_or(latest_snapshot);
+
+        nopt.nes_mut().load_snapshot(&snapshot.data);
+        let first_input_index =
+            usize::try_from(snapshot.frame - self.oldest_frame().unwrap()).unwrap();
+        let input_count = usize::try_from(target_frame - snapshot.frame).unwrap();
+        for input_index in first_input_index..first_input_index + input_count {
+            nopt.run_frame_with_buttons(self.inputs[input_index]);
+        }
+
+        self.inputs.pop_back();
+        self.frame = target_frame;
+        true
+    }
+
+    fn take_snapshot<Cartridge: cartridge::Cartridge>(&mut self, nopt: &Nopt<Cartridge>) {
+        let data = nopt.nes().save_snapshot();
+        if let Some(previous_snapshot) = self.latest_snapshot.take() {
+            let encoding = match encode_delta(&data, &previous_snapshot.data) {
+                Some(delta) => Encoding::Delta(delta),
+                None => Encoding::Keyframe(previous_snapshot.data),
+            };
+            self.older_snapshots.push_back(EncodedSnapshot {
+                frame: previous_snapshot.frame,
+                encoding,
+            });
+        }
+        self.latest_snapshot = Some(Snapshot {
+            frame: self.frame,
+            data,
+        });
+
+        while self.memory_usage() > self.memory_limit {
+            let Some(oldest_snapshot) = self.older_snapshots.pop_front() else {
+                break;
+            };
+            let discarded_frames = self.oldest_frame().unwrap() - oldest_snapshot.frame;
+            self.inputs
+                .drain(..usize::try_from(discarded_frames).unwrap());
+        }
+    }
+
+    fn oldest_frame(&self) -> Option<u64> {
+        self.older_snapshots
+            .front()
+            .map(|snapshot| snapshot.frame)
+            .or(self.latest_snapshot.as_ref().map(|snapshot| snapshot.frame))
+    }
+}
+
+/// Encodes `target` relative to `base` as a sequence of runs. Each run
+/// consists of the number of unchanged bytes to skip, the number of changed
+/// bytes, and the changed bytes themselves. Returns `None` if the lengths
+/// differ, as only changes in place can be encoded.
+fn encode_delta(base: &[u8], target: &[u8]) -> Option<Vec<u8>> {
+    if base.len() != target.len() {
+        return None;
+    }
+
+    let mut delta = Vec::new();
+    let mut index = 0;
+    while index < target.len() {
+        let run_start = index;
+        while index < target.len() && base[index] == target[index] {
+            index += 1;
+        }
+        let changes_start = index;
+        while index < target.len() && base[index] != target[index] {
+            index += 1;
+        }
+
+        let skipped = u32::try_from(changes_start - run_start).unwrap();
+        let changed = u32::try_from(index - changes_start).unwrap();
+        delta.extend_from_slice(&skipped.to_le_bytes());
+        delta.extend_from_slice(&changed.to_le_bytes());
+        delta.extend_from_slice(&target[changes_start..index]);
+    }
+    Some(delta)
+}
+
+fn apply_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut target = base.to_vec();
+    let mut index = 0;
+    let mut delta = delta;
+    while !delta.is_empty() {
+        let (skipped, rest) = delta.split_at(size_of::<u32>());
+        let (changed, rest) = rest.split_at(size_of::<u32>());
+        let skipped = usize::try_from(u32::from_le_bytes(skipped.try_into().unwrap())).unwrap();
+        let changed = usize::try_from(u32::from_le_bytes(changed.try_into().unwrap())).unwrap();
+        let (changes, rest) = rest.split_at(changed);
+
+        index += skipped;
+        target[index..index + changed].copy_from_slice(changes);
+        index += changed;
+        delta = rest;
+    }
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    // SEI; loop: INC $00; set the backdrop color to the value at $00; JMP loop
+    const PROGRAM: [u8; 21] = [
+        0x78, 0xe6, 0x00, 0xa9, 0x3f, 0x8d, 0x06, 0x20, 0xa9, 0x00, 0x8d, 0x06, 0x20, 0xa5, 0x00,
+        0x8d, 0x07, 0x20, 0x4c, 0x01, 0x80,
+    ];
+
+    fn buttons(frame: u8) -> [Buttons; 2] {
+        [Buttons::from_bits_retain(frame), Buttons::empty()]
+    }
+
+    #[test]
+    fn delta_round_trips() {
+        let base = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        for target in [
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            [9, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            [0, 1, 2, 3, 4, 5, 6, 7, 8, 0],
+            [0, 1, 0, 0, 4, 5, 0, 7, 0, 9],
+            [9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+        ] {
+            let delta = encode_delta(&base, &target).unwrap();
+            assert_eq!(apply_delta(&base, &delta), target);
+        }
+        assert_eq!(encode_delta(&base, &base[1..]), None);
+    }
+
+    #[test]
+    fn stepping_back_restores_earlier_frames() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        let mut rewind = Rewind::new(4, usize::MAX);
+        let initial_cpu = nopt.nes().cpu.clone();
+        let mut states = Vec::new();
+        for frame in 0..10 {
+            states.push(nopt.save_state());
+            rewind.run_frame(&mut nopt, buttons(frame));
+        }
+        assert_eq!(rewind.available_frames(), 10);
+
+        // crossing the snapshots taken at frames 8 and 4
+        for frame in (1..10).rev() {
+            assert!(rewind.step_back(&mut nopt));
+            assert_eq!(nopt.save_state(), states[frame], "frame {frame}");
+        }
+        // the picture of the oldest frame is not recorded
+        assert!(rewind.step_back(&mut nopt));
+        assert_eq!(nopt.nes().cpu.ram, initial_cpu.ram);
+        assert_eq!(nopt.nes().cpu.pc, initial_cpu.pc);
+        assert!(!rewind.step_back(&mut nopt));
+    }
+
+    #[test]
+    fn running_after_stepping_back_records_new_history() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        let mut rewind = Rewind::new(4, usize::MAX);
+        for frame in 0..6 {
+            rewind.run_frame(&mut nopt, buttons(frame));
+        }
+        for _ in 0..3 {
+            rewind.step_back(&mut nopt);
+        }
+        let state = nopt.save_state();
+        for frame in 3..6 {
+            rewind.run_frame(&mut nopt, buttons(frame + 10));
+        }
+        assert_eq!(rewind.available_frames(), 6);
+
+        for _ in 0..3 {
+            rewind.step_back(&mut nopt);
+        }
+        assert_eq!(nopt.save_state(), state);
+    }
+
+    #[test]
+    fn oldest_history_is_discarded_over_memory_limit() {
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        let mut rewind = Rewind::new(2, usize::MAX);
+        rewind.run_frame(&mut nopt, buttons(0));
+        let snapshot_size = rewind.memory_usage();
+
+        // only leaves room for a few deltas besides the latest snapshot
+        let mut rewind = Rewind::new(2, snapshot_size + 64);
+        let mut nopt = test_utils::nopt(&PROGRAM);
+        for frame in 0..20 {
+            rewind.run_frame(&mut nopt, buttons(frame));
+        }
+        assert!(rewind.available_frames() < 20);
+        assert!(rewind.memory_usage() <= snapshot_size + 64);
+        while rewind.step_back(&mut nopt) {}
+        assert_eq!(rewind.available_frames(), 0);
+    }
+}
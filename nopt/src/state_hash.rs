@@ -0,0 +1,186 @@
+use crate::{cartridge, compiler::frontend::nes::Nes};
+use std::io::{self, Write};
+
+/// How often the state of the machine is hashed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashInterval {
+    Frames(u64),
+    Cycles(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StateHash {
+    /// The number of frames completed when the hash was taken.
+    pub frame: u64,
+    pub cycle: u64,
+    pub hash: u64,
+}
+
+/// A log of hashes of the machine state taken at regular intervals, for
+/// finding where two runs which should be identical diverge.
+///
+/// The hashed state is the one included in save states. Hashes are taken at
+/// the first instruction boundary after each interval has elapsed.
+#[derive(Clone)]
+pub struct StateHashLog {
+    interval: HashInterval,
+    next_checkpoint: Option<u64>,
+    hashes: Vec<StateHash>,
+}
+
+impl StateHashLog {
+    /// # Panics
+    ///
+    /// Panics if the interval is zero.
+    #[must_use]
+    pub fn new(interval: HashInterval) -> Self {
+        let (HashInterval::Frames(length) | HashInterval::Cycles(length)) = interval;
+        assert!(length > 0, "hash interval must be positive");
+        Self {
+            interval,
+            next_checkpoint: None,
+            hashes: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn hashes(&self) -> &[StateHash] {
+        &self.hashes
+    }
+
+    /// The index of the first hash which differs from the other log, or at
+    /// which one of the logs ends early.
+    #[must_use]
+    pub fn first_divergence(&self, other: &Self) -> Option<usize> {
+        let index = self
+            .hashes
+            .iter()
+            .zip(&other.hashes)
+            .position(|(hash, other_hash)| hash != other_hash)
+            .unwrap_or(self.hashes.len().min(other.hashes.len()));
+        (index < self.hashes.len().max(other.hashes.len())).then_some(index)
+    }
+
+    /// Writes the log as text, one hash per line.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error encountered while writing.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        for hash in &self.hashes {
+            writeln!(
+                writer,
+                "frame {} cycle {} hash {:016x}",
+                hash.frame, hash.cycle, hash.hash
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Called after every instruction, hashing the state if the current
+    /// interval has elapsed.
+    pub(crate) fn update<Cartridge: cartridge::Cartridge>(&mut self, nes: &Nes<Cartridge>) {
+        let (position, length) = match self.interval {
+            HashInterval::Frames(length) => (nes.ppu.frame_count, length),
+            HashInterval::Cycles(length) => (nes.cpu.cycle_count, length),
+        };
+        let next_checkpoint = self
+            .next_checkpoint
+            .get_or_insert(position - position % length + length);
+        if position < *next_checkpoint {
+            return;
+        }
+        while position >= *next_checkpoint {
+            *next_checkpoint += length;
+        }
+
+        self.hashes.push(StateHash {
+            frame: nes.ppu.frame_count,
+            cycle: nes.cpu.cycle_count,
+            hash: fnv1a(&nes.save_state()),
+        });
+    }
+}
+
+/// A hash which is stable across platforms and compiler versions, unlike the
+/// one of [`std::hash::DefaultHasher`].
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Nopt, cartridge::Nrom, test_utils};
+
+    // SEI; loop: INC $00; JMP loop
+    const PROGRAM: [u8; 6] = [0x78, 0xe6, 0x00, 0x4c, 0x01, 0x80];
+
+    fn log(hashes: &[u64]) -> StateHashLog {
+        let mut log = StateHashLog::new(HashInterval::Frames(1));
+        log.hashes = hashes
+            .iter()
+            .zip(1..)
+            .map(|(&hash, frame)| StateHash {
+                frame,
+                cycle: frame * 29781,
+                hash,
+            })
+            .collect();
+        log
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn first_divergence_finds_differing_hash() {
+        assert_eq!(log(&[1, 2, 3]).first_divergence(&log(&[1, 2, 3])), None);
+        assert_eq!(log(&[]).first_divergence(&log(&[])), None);
+        assert_eq!(log(&[1, 2, 3]).first_divergence(&log(&[1, 5, 3])), Some(1));
+        assert_eq!(log(&[1, 2, 3]).first_divergence(&log(&[4, 2, 3])), Some(0));
+    }
+
+    #[test]
+    fn first_divergence_finds_early_end() {
+        assert_eq!(log(&[1, 2, 3]).first_divergence(&log(&[1, 2])), Some(2));
+        assert_eq!(log(&[1]).first_divergence(&log(&[1, 2])), Some(1));
+        assert_eq!(log(&[]).first_divergence(&log(&[1])), Some(0));
+    }
+
+    fn run_frames(nopt: &mut Nopt<Nrom>, count: usize) -> &StateHashLog {
+        for _ in 0..count {
+            nopt.run_frame();
+        }
+        nopt.state_hash_log().unwrap()
+    }
+
+    #[test]
+    fn runs_diverge_where_state_differs() {
+        let mut first = test_utils::nopt(&PROGRAM);
+        let mut second = test_utils::nopt(&PROGRAM);
+        first.set_state_hash_log(Some(StateHashLog::new(HashInterval::Frames(1))));
+        second.set_state_hash_log(Some(StateHashLog::new(HashInterval::Frames(1))));
+        let hash_count = run_frames(&mut first, 4).hashes().len();
+        assert_eq!(
+            run_frames(&mut first, 0).first_divergence(run_frames(&mut second, 4)),
+            None
+        );
+
+        second.nes_mut().cpu.ram[0x100] = 1;
+        let first_log = run_frames(&mut first, 1);
+        assert!(first_log.hashes().windows(2).all(|hashes| {
+            hashes[1].frame == hashes[0].frame + 1 && hashes[1].cycle > hashes[0].cycle
+        }));
+        assert_eq!(
+            first_log.first_divergence(run_frames(&mut second, 1)),
+            Some(hash_count)
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use crate::{Nopt, cartridge::Nrom};
+
+/// Creates an NROM cartridge with CHR RAM, holding the given program at
+/// $8000 and the NMI and IRQ handlers at $9000 and $9800 respectively.
+pub(crate) fn nrom(program: &[u8], nmi_handler: &[u8], irq_handler: &[u8]) -> Nrom {
+    Nrom::new(
+        &prg_rom(program, nmi_handler, irq_handler),
+        &[],
+        false,
+        None,
+    )
+}
+
+pub(crate) fn prg_rom(program: &[u8], nmi_handler: &[u8], irq_handler: &[u8]) -> Vec<u8> {
+    let mut prg_rom = vec![0; 0x4000];
+    prg_rom[..program.len()].copy_from_slice(program);
+    prg_rom[0x1000..][..nmi_handler.len()].copy_from_slice(nmi_handler);
+    prg_rom[0x1800..][..irq_handler.len()].copy_from_slice(irq_handler);
+    prg_rom[0x3ffa..].copy_from_slice(&[0x00, 0x90, 0x00, 0x80, 0x00, 0x98]);
+    prg_rom
+}
+
+/// Creates a machine running the given program, whose interrupt handlers
+/// return immediately.
+pub(crate) fn nopt(program: &[u8]) -> Nopt<Nrom> {
+    Nopt::new(nrom(program, &[0x40], &[0x40]))
+}
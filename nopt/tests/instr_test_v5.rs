@@ -38,9 +38,7 @@ fn instr_test_v5() {
 
         let result_code;
         loop {
-            unsafe {
-                nopt.run();
-            }
+            nopt.run();
 
             let data_is_valid = (0x6001..0x6004)
                 .map(|address| nopt.nes().peek(address))
@@ -55,8 +55,6 @@ fn nestest() {
             assert_eq!(nopt_log_line, log_line);
         }
 
-        unsafe {
-            nopt.run();
-        }
+        nopt.run();
     }
 }
@@ -4,7 +4,6 @@ use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _};
 
 #[test]
-#[ignore = "unimplemented"]
 fn ppu_read_buffer() {
     tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer())
@@ -27,9 +26,7 @@ fn ppu_read_buffer() {
 
     let result_code;
     loop {
-        unsafe {
-            nopt.run();
-        }
+        nopt.run();
 
         let data_is_valid = (0x6001..0x6004)
             .map(|address| nopt.nes().peek(address))